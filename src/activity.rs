@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use url::Url;
 
 // https://github.com/go-gitea/gitea/blob/921d3a394de244de83650fa5dcc4866b085cf72b/models/activities/action.go#L66
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum OpType {
     CreateRepo,
@@ -35,7 +35,7 @@ pub enum OpType {
     AutoMergePullRequest,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Activity {
     pub op_type: OpType,
     pub date: DateTime<chrono::FixedOffset>,
@@ -45,7 +45,76 @@ pub struct Activity {
     pub email: String,
 }
 
-#[derive(Deserialize, Serialize, Eq, Hash, PartialEq)]
+impl Activity {
+    /// Identity of the activity on the source platform, stable between runs
+    pub fn key(&self) -> String {
+        match (&self.op_type, &self.content) {
+            (_, ActivityContent::Commit(c)) => format!("commit/{}", c.sha1),
+            (_, ActivityContent::Comment(c)) => format!("comment/{}", c.comment_id),
+            (_, ActivityContent::PatchSet(p)) => {
+                format!("pull/{}/patchset/{}", p.issue_id, p.patch_set)
+            }
+            (OpType::CreatePullRequest, ActivityContent::Issue(i)) => {
+                format!("pull/{}", i.issue_id)
+            }
+            (OpType::MergePullRequest, ActivityContent::Issue(i)) => {
+                format!("pull/{}/merged", i.issue_id)
+            }
+            (OpType::ClosePullRequest, ActivityContent::Issue(i)) => {
+                format!("pull/{}/closed", i.issue_id)
+            }
+            (OpType::ApprovePullRequest, ActivityContent::Issue(i)) => {
+                format!("pull/{}/approved", i.issue_id)
+            }
+            (OpType::RejectPullRequest, ActivityContent::Issue(i)) => {
+                format!("pull/{}/rejected", i.issue_id)
+            }
+            (OpType::CloseIssue, ActivityContent::Issue(i)) => {
+                format!("issue/{}/closed", i.issue_id)
+            }
+            (_, ActivityContent::Issue(i)) => format!("issue/{}", i.issue_id),
+        }
+    }
+
+    /// If the activity opens an issue or pull request, which is mirrored as an issue. Everything
+    /// else that isn't a commit is mirrored as a commit describing it
+    pub fn is_opening(&self) -> bool {
+        matches!(self.content, ActivityContent::Issue(_))
+            && matches!(
+                self.op_type,
+                OpType::CreateIssue | OpType::CreatePullRequest
+            )
+    }
+
+    /// What happened, like "Merged pull request #3: Title"
+    pub fn describe(&self) -> String {
+        let (id, title) = match &self.content {
+            ActivityContent::Commit(c) => return c.message.clone(),
+            ActivityContent::Issue(i) => (i.issue_id, &i.message),
+            ActivityContent::Comment(c) => (c.issue_id, &c.message),
+            ActivityContent::PatchSet(p) => {
+                return format!(
+                    "Uploaded patch set {} of pull request #{}: {}",
+                    p.patch_set, p.issue_id, p.message
+                )
+            }
+        };
+        let action = match self.op_type {
+            OpType::CreatePullRequest => "Opened pull request",
+            OpType::MergePullRequest => "Merged pull request",
+            OpType::ClosePullRequest => "Closed pull request",
+            OpType::ApprovePullRequest => "Approved pull request",
+            OpType::RejectPullRequest => "Requested changes on pull request",
+            OpType::CommentPull => "Commented on pull request",
+            OpType::CloseIssue => "Closed issue",
+            OpType::CommentIssue => "Commented on issue",
+            _ => "Opened issue",
+        };
+        format!("{} #{}: {}", action, id, title)
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Eq, Hash, PartialEq)]
 pub struct Repository {
     // If it is owned under you, or if it is a repo under an organization or a friend
     pub owned_by_you: bool,
@@ -59,15 +128,18 @@ pub struct Repository {
     pub created_date: DateTime<chrono::FixedOffset>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum ActivityContent {
     Commit(Commit),
+    // Before Issue, as it has the same fields and more
+    Comment(Comment),
+    PatchSet(PatchSet),
     Issue(Issue),
     // Other activity types...
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Commit {
     pub sha1: String,
     pub message: String,
@@ -76,8 +148,97 @@ pub struct Commit {
     pub timestamp: DateTime<chrono::FixedOffset>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Issue {
     pub issue_id: u64,
     pub message: String,
 }
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Comment {
+    pub comment_id: u64,
+    /// The issue or pull request that was commented on
+    pub issue_id: u64,
+    /// Title of the issue or pull request
+    pub message: String,
+}
+
+/// A new version of a pull request, like the patch sets of a Gerrit change
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PatchSet {
+    /// Number of the version, counting from 1
+    pub patch_set: u64,
+    pub issue_id: u64,
+    /// Title of the pull request
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(op_type: OpType, content: ActivityContent) -> Activity {
+        Activity {
+            op_type,
+            date: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap(),
+            content,
+            source_link: Url::parse("https://example.com/c/project/+/3").unwrap(),
+            username: "me".to_string(),
+            email: "me@example.com".to_string(),
+        }
+    }
+
+    fn issue(issue_id: u64, message: &str) -> ActivityContent {
+        ActivityContent::Issue(Issue {
+            issue_id,
+            message: message.to_string(),
+        })
+    }
+
+    #[test]
+    fn review_keys_are_per_pull_request() {
+        assert_eq!(
+            activity(OpType::ApprovePullRequest, issue(3, "Title")).key(),
+            "pull/3/approved"
+        );
+        assert_eq!(
+            activity(OpType::RejectPullRequest, issue(3, "Title")).key(),
+            "pull/3/rejected"
+        );
+        assert_eq!(
+            activity(OpType::CreatePullRequest, issue(3, "Title")).key(),
+            "pull/3"
+        );
+    }
+
+    #[test]
+    fn keys_stay_the_same_when_the_title_or_date_change() {
+        let mut approved = activity(OpType::ApprovePullRequest, issue(3, "Title"));
+        let key = approved.key();
+
+        approved.content = issue(3, "Renamed");
+        approved.date = DateTime::parse_from_rfc3339("2024-06-01T00:00:00+02:00").unwrap();
+        assert_eq!(approved.key(), key);
+
+        assert_ne!(
+            activity(OpType::ApprovePullRequest, issue(4, "Title")).key(),
+            key
+        );
+    }
+
+    #[test]
+    fn patch_set_keys_have_the_patch_set_number() {
+        let patch_set = |patch_set| {
+            activity(
+                OpType::CommitRepo,
+                ActivityContent::PatchSet(PatchSet {
+                    patch_set,
+                    issue_id: 3,
+                    message: "Title".to_string(),
+                }),
+            )
+        };
+        assert_eq!(patch_set(2).key(), "pull/3/patchset/2");
+        assert_ne!(patch_set(2).key(), patch_set(3).key());
+    }
+}
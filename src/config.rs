@@ -2,24 +2,21 @@
 #![allow(dead_code)]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{fs, path::PathBuf};
-use toml;
 
-use crate::services::ServiceConfig;
+use crate::services::{ServiceConfig, ServiceType};
+use crate::targets::{TargetConfig, TargetType};
 
-#[derive(PartialEq, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum PushMethod {
     Http,
+    #[default]
     Ssh,
 }
-impl Default for PushMethod {
-    fn default() -> Self {
-        PushMethod::Ssh
-    }
-}
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Default)]
 pub enum RedactLevel {
     Off,
+    #[default]
     PrivateRepos,
     PrivateReposNoCrossLinking,
     Encrypted, // TODO: These will be properly implemented in the future
@@ -62,15 +59,11 @@ impl<'de> Deserialize<'de> for RedactLevel {
     }
 }
 
-impl Default for RedactLevel {
-    fn default() -> Self {
-        RedactLevel::PrivateRepos
-    }
-}
-
 #[derive(Deserialize, Serialize, Clone)]
 pub struct GitConfig {
     pub username: String,
+    /// Not needed for local targets
+    #[serde(default)]
     pub token: String,
     pub email: Option<String>,
     #[serde(default)]
@@ -82,15 +75,106 @@ pub struct GitConfig {
 #[derive(Deserialize, Serialize)]
 pub struct Config {
     pub services: Vec<ServiceConfig>,
-    pub github: GitConfig,
+    /// Shorthand for a GitHub target, added in front of the other targets when loading
+    pub github: Option<GitConfig>,
+    /// Where the activities are mirrored to
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>,
+    /// Where to keep track of what has already been mirrored
+    #[serde(default = "default_ledger")]
+    pub ledger: PathBuf,
+    /// How many services to fetch from, and repos to sync, at the same time
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct WebhookConfig {
+    /// Address to listen for webhooks on
+    #[serde(default = "default_listen")]
+    pub listen: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DaemonConfig {
+    /// Seconds between each sync
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Up to this many seconds are randomly added to each interval
+    #[serde(default = "default_jitter")]
+    pub jitter: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_interval(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+fn default_listen() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
+fn default_interval() -> u64 {
+    60 * 60
+}
+
+fn default_jitter() -> u64 {
+    5 * 60
+}
+
+fn default_ledger() -> PathBuf {
+    PathBuf::from("./ledger.json")
+}
+
+fn default_concurrency() -> usize {
+    4
 }
 
 impl Config {
     pub fn load(path: PathBuf) -> anyhow::Result<Self> {
         let config_content = fs::read_to_string(path)?;
         let mut config: Config = toml::from_str(&config_content)?;
+        if config.concurrency == 0 {
+            anyhow::bail!("concurrency must be at least 1");
+        }
+        if config.daemon.interval == 0 {
+            anyhow::bail!("daemon.interval must be at least 1 second");
+        }
+
+        if let Some(github) = config.github.take() {
+            config.targets.insert(
+                0,
+                TargetConfig {
+                    target_type: TargetType::Github,
+                    url: None,
+                    path: None,
+                    git: github,
+                },
+            );
+        }
+        if config.targets.is_empty() {
+            anyhow::bail!("No targets to mirror to. Add a [github] section or [[targets]]");
+        }
+        for target in &config.targets {
+            if target.target_type != TargetType::Local && target.git.token.is_empty() {
+                anyhow::bail!("{:?} targets need a token", target.target_type);
+            }
+        }
 
         for service in &mut config.services {
+            if service.redmine.is_some() && !matches!(service.service_type, ServiceType::Redmine) {
+                anyhow::bail!(
+                    "[services.redmine] is only for Redmine, not {:?}",
+                    service.service_type
+                );
+            }
             service.init_client()?
         }
 
@@ -0,0 +1,87 @@
+use rand::Rng;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{config::Config, error::Error, ledger::Ledger, mirror::Mirrors, report::Report};
+
+/// Keep syncing on an interval, until SIGINT/SIGTERM is received
+pub async fn run(
+    config: Arc<Config>,
+    mirrors: Arc<Mirrors>,
+    ledger: Arc<Mutex<Ledger>>,
+) -> Result<(), Error> {
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    let mut cycle: Option<JoinHandle<Report>> = None;
+
+    loop {
+        if cycle.as_ref().is_some_and(|cycle| !cycle.is_finished()) {
+            println!("Previous sync is still running, skipping this cycle");
+        } else {
+            if let Some(previous) = cycle.take() {
+                report(previous).await;
+            }
+
+            let config = config.clone();
+            let mirrors = mirrors.clone();
+            let ledger = ledger.clone();
+            cycle = Some(tokio::spawn(async move {
+                let (repos, mut report) = crate::fetch_repos(&config).await;
+                report.extend(mirrors.sync(repos, &ledger).await);
+                report
+            }));
+        }
+
+        let jitter = match config.daemon.jitter {
+            0 => 0,
+            jitter => rand::rng().random_range(0..=jitter),
+        };
+        let delay = Duration::from_secs(config.daemon.interval + jitter);
+        println!("Next sync in {} seconds", delay.as_secs());
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut shutdown => break,
+        }
+    }
+
+    // Let the repos that are being synced finish, so nothing is left half-pushed
+    mirrors.stop();
+    if let Some(cycle) = cycle {
+        if !cycle.is_finished() {
+            println!("Shutting down, waiting for the current sync to finish");
+        }
+        report(cycle).await;
+    }
+    println!("Shut down");
+
+    Ok(())
+}
+
+async fn report(cycle: JoinHandle<Report>) {
+    match cycle.await {
+        Ok(report) => println!("\n{}", report),
+        Err(e) => eprintln!("Sync panicked: {}", e),
+    }
+}
+
+/// Resolves once SIGINT or SIGTERM is received
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate =
+            signal(SignalKind::terminate()).expect("Could not listen for SIGTERM signals");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -0,0 +1,70 @@
+use reqwest::StatusCode;
+use std::process::ExitCode;
+
+use crate::http::GithubLimit;
+
+const EXIT_SERVER_FAILURE: u8 = 3;
+
+/// Everything that can make a repo or service fail to sync
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Authentication failed: {0:#}")]
+    Auth(anyhow::Error),
+    #[error("Rate limited: {0:#}")]
+    RateLimit(anyhow::Error),
+    #[error("Git failed: {0:#}")]
+    Git(anyhow::Error),
+    #[error("Source API failed: {0:#}")]
+    SourceApi(anyhow::Error),
+    #[error("Mirror API failed: {0:#}")]
+    MirrorApi(anyhow::Error),
+    #[error("Invalid config: {0:#}")]
+    Config(anyhow::Error),
+    #[error("Ledger failed: {0:#}")]
+    Ledger(anyhow::Error),
+    /// The daemon or the webhook receiver stopped, like when its port is taken
+    #[error("Server failed: {0:#}")]
+    Server(anyhow::Error),
+}
+
+impl Error {
+    /// Classify an error coming from a `ServiceClient`
+    pub fn source_api(e: anyhow::Error) -> Self {
+        Self::classify(e, Error::SourceApi)
+    }
+
+    /// Classify an error coming from the API of a `MirrorTarget`
+    pub fn mirror_api(e: anyhow::Error) -> Self {
+        Self::classify(e, Error::MirrorApi)
+    }
+
+    /// Exit code when the error ends the run
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Server(_) => ExitCode::from(EXIT_SERVER_FAILURE),
+            _ => ExitCode::FAILURE,
+        }
+    }
+
+    fn classify(e: anyhow::Error, otherwise: fn(anyhow::Error) -> Self) -> Self {
+        match e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Error::Auth(e),
+            Some(StatusCode::TOO_MANY_REQUESTS) => Error::RateLimit(e),
+            _ => otherwise(e),
+        }
+    }
+}
+
+impl From<octocrab::Error> for Error {
+    fn from(e: octocrab::Error) -> Self {
+        match &e {
+            octocrab::Error::GitHub { source, .. }
+                if source.status_code == StatusCode::UNAUTHORIZED =>
+            {
+                Error::Auth(e.into())
+            }
+            _ if GithubLimit::of(&e).is_some() => Error::RateLimit(e.into()),
+            _ => Error::MirrorApi(e.into()),
+        }
+    }
+}
@@ -1,80 +1,99 @@
 use anyhow::{Context, Result};
 use chrono::DateTime;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
 
-use crate::activity::{self, Activity};
+use crate::activity;
 use crate::config::{GitConfig, PushMethod};
+use crate::targets::MirrorRepo;
 
-const MARK_STRING: &str = "<sub>This repo was mirrored using [github-activity-mirror](https://codeberg.org/Aadniz/github-activity-mirror), preserving the privacy while at the same time display your actual activity</sub>";
+pub const MARK_STRING: &str = "<sub>This repo was mirrored using [github-activity-mirror](https://codeberg.org/Aadniz/github-activity-mirror), preserving the privacy while at the same time display your actual activity</sub>";
 const BRANCH: &str = "main";
 
 pub struct Git {
     config: GitConfig,
+    /// Where the mirror repos are cloned to
+    workspace: PathBuf,
 }
 impl Git {
-    pub fn new(config: GitConfig) -> Self {
-        Self { config }
+    pub fn new(config: GitConfig, workspace: PathBuf) -> Self {
+        Self { config, workspace }
     }
 
-    pub fn get_path(&self, repo: &octocrab::models::Repository) -> PathBuf {
-        let temp_dir = std::env::temp_dir();
-        let repo_name = repo
-            .full_name
-            .as_ref()
-            .context("No repository name")
-            .unwrap();
-        let repo_path = temp_dir.join(repo_name.replace('/', "_"));
-
-        repo_path
+    pub fn get_path(&self, repo: &MirrorRepo) -> PathBuf {
+        self.workspace.join(repo.full_name.replace('/', "_"))
     }
 
-    pub fn create_init(
+    pub async fn create_init(
         &self,
-        repo: &octocrab::models::Repository,
-        activity: &Activity,
+        repo: &MirrorRepo,
+        date: DateTime<chrono::FixedOffset>,
     ) -> Result<()> {
-        let repo_path = self.initialize_local_git(repo)?;
+        let repo_path = self.initialize_local_git(repo).await?;
 
         // Create README.md file
         std::fs::write(repo_path.join("README.md"), MARK_STRING)?;
 
         // Stage the file
-        self.run_git_command(&repo_path, &["add", "README.md"])?;
+        run_git_command(&repo_path, &["add", "README.md"]).await?;
 
         // Create commit with specific date
-        self.commit(repo, "Initial commit".to_string(), activity.date)?;
+        self.commit(repo, "Initial commit".to_string(), date)
+            .await?;
 
         // Push the changes
-        self.push(&repo)?;
+        self.push(repo).await?;
+
+        Ok(())
+    }
 
+    /// Create an empty bare repo, to push mirrors to
+    pub async fn init_bare(&self, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(path)?;
+        run_git_command(path, &["init", "--bare", "--initial-branch", BRANCH]).await?;
         Ok(())
     }
 
-    pub fn push(&self, repo: &octocrab::models::Repository) -> Result<()> {
-        let repo_path = self.initialize_local_git(repo)?;
-        self.run_git_command(&repo_path, &["push", "--set-upstream", "origin", BRANCH])?;
+    /// Content of a file on the main branch of a bare repo, if it is there
+    pub async fn read_bare_file(&self, path: &Path, file: &str) -> Result<Option<String>> {
+        let output = Command::new("git")
+            .current_dir(path)
+            .args(["show", &format!("{}:{}", BRANCH, file)])
+            .output()
+            .await
+            .context("Failed to execute git show")?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    pub async fn push(&self, repo: &MirrorRepo) -> Result<()> {
+        let repo_path = self.get_path(repo);
+        run_git_command(&repo_path, &["push", "--set-upstream", "origin", BRANCH]).await?;
         Ok(())
     }
 
-    fn initialize_local_git(&self, repo: &octocrab::models::Repository) -> Result<PathBuf> {
-        let temp_dir = std::env::temp_dir();
-        let repo_name = repo.full_name.as_ref().context("No repository name")?;
-        let repo_path = temp_dir.join(repo_name.replace('/', "_"));
+    pub async fn initialize_local_git(&self, repo: &MirrorRepo) -> Result<PathBuf> {
+        let repo_path = self.get_path(repo);
 
         // Clone the repository
         if repo_path.exists() {
-            let _ = self.run_git_command(&repo_path, &["fetch", "origin"]);
+            run_git_command(&repo_path, &["fetch", "origin"]).await?;
 
             let branch_check = Command::new("git")
                 .current_dir(&repo_path)
                 .args(["ls-remote", "--heads", "origin", BRANCH])
-                .output();
+                .output()
+                .await;
 
             if let Ok(output) = branch_check {
                 if !output.stdout.is_empty() {
-                    // Only pull if main branch exists
-                    self.run_git_command(&repo_path, &["pull", "origin", BRANCH])?;
+                    // Only reset if main branch exists, dropping any commits left over from a
+                    // sync that failed before they were pushed
+                    let remote_branch = format!("origin/{}", BRANCH);
+                    run_git_command(&repo_path, &["reset", "--hard", &remote_branch]).await?;
                 }
             }
             return Ok(repo_path);
@@ -92,21 +111,23 @@ impl Git {
                 .context("No SSH URL available")?
                 .to_string(),
         };
-        self.run_git_command(
-            &temp_dir,
+        std::fs::create_dir_all(&self.workspace)?;
+        run_git_command(
+            &self.workspace,
             &["clone", &clone_url, repo_path.to_str().unwrap()],
-        )?;
+        )
+        .await?;
 
         Ok(repo_path)
     }
 
-    pub fn add_commit(
+    pub async fn add_commit(
         &self,
-        repo: &octocrab::models::Repository,
+        repo: &MirrorRepo,
         commit_message: String,
         commit_content: String,
         date: DateTime<chrono::FixedOffset>,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<String> {
         let repo_path = self.get_path(repo);
 
         // Update README
@@ -115,15 +136,21 @@ impl Git {
             format!("{}\n\n{}", commit_content, MARK_STRING),
         )?;
 
-        self.run_git_command(&repo_path, &["add", "README.md"])?;
-        self.commit(repo, commit_message, date)?;
+        run_git_command(&repo_path, &["add", "README.md"]).await?;
+        self.commit(repo, commit_message, date).await?;
 
-        Ok(())
+        self.head_sha(repo).await
+    }
+
+    pub async fn head_sha(&self, repo: &MirrorRepo) -> anyhow::Result<String> {
+        let repo_path = self.get_path(repo);
+        let sha = run_git_command(&repo_path, &["rev-parse", "HEAD"]).await?;
+        Ok(sha.trim().to_string())
     }
 
-    fn commit(
+    async fn commit(
         &self,
-        repo: &octocrab::models::Repository,
+        repo: &MirrorRepo,
         commit_message: String,
         date: DateTime<chrono::FixedOffset>,
     ) -> anyhow::Result<()> {
@@ -147,6 +174,7 @@ impl Git {
             .env("GIT_COMMITTER_DATE", &date_str)
             .args(args)
             .output()
+            .await
             .context("Failed to execute git commit")?;
 
         if !output.status.success() {
@@ -174,11 +202,8 @@ impl Git {
         Ok(())
     }
 
-    pub fn last_commit(
-        &self,
-        repo: &octocrab::models::Repository,
-    ) -> anyhow::Result<activity::Commit> {
-        let repo_path = self.initialize_local_git(repo)?;
+    pub async fn last_commit(&self, repo: &MirrorRepo) -> anyhow::Result<activity::Commit> {
+        let repo_path = self.initialize_local_git(repo).await?;
 
         // Get the last commit details using git log
         let output = Command::new("git")
@@ -189,6 +214,7 @@ impl Git {
                 "--pretty=format:%H|%ae|%an|%aI|%s", // Format: sha|email|name|iso-date|message
             ])
             .output()
+            .await
             .context("Failed to execute git log command")?;
 
         if !output.status.success() {
@@ -227,7 +253,7 @@ impl Git {
         })
     }
 
-    pub fn unpushed_commits(&self, repo: &octocrab::models::Repository) -> anyhow::Result<u32> {
+    pub async fn unpushed_commits(&self, repo: &MirrorRepo) -> anyhow::Result<u32> {
         let repo_path = self.get_path(repo);
 
         // Check for unpushed commits
@@ -235,6 +261,7 @@ impl Git {
             .current_dir(&repo_path)
             .args(["cherry", "-v", &format!("origin/{}", BRANCH)])
             .output()
+            .await
             .context("Failed to execute git cherry command")?;
 
         if !output.status.success() {
@@ -251,25 +278,64 @@ impl Git {
 
         Ok(count)
     }
+}
 
-    fn run_git_command(&self, cwd: &PathBuf, args: &[&str]) -> Result<()> {
-        let output = Command::new("git")
-            .current_dir(cwd)
-            .args(args)
-            .output()
-            .context("Failed to execute git command")?;
+/// Run git in a directory, giving what it wrote
+pub async fn run_git_command(cwd: &Path, args: &[&str]) -> Result<String> {
+    run_git(cwd, args, &[]).await
+}
 
-        if !output.status.success() {
-            anyhow::bail!(
-                "Git command '{}$ git {}' failed with exit code {}\nstdout: {}\nstderr: {}",
-                cwd.as_os_str().to_str().unwrap(),
-                args.join(" "),
-                output.status.code().unwrap(),
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
+/// Same as `run_git_command`, giving git the username and the password when the remote asks for
+/// them. They are passed through the environment to a credential helper, so they end up neither
+/// in the arguments nor in the config of the repo
+pub async fn run_git_command_with_credentials(
+    cwd: &Path,
+    args: &[&str],
+    username: &str,
+    password: &str,
+) -> Result<String> {
+    let helper = "!f() { test \"$1\" = get && printf 'username=%s\\npassword=%s\\n' \
+                  \"$MIRROR_GIT_USERNAME\" \"$MIRROR_GIT_PASSWORD\"; }; f";
+    let envs = [
+        ("GIT_CONFIG_COUNT", "2"),
+        // The empty helper drops the ones set up by the user
+        ("GIT_CONFIG_KEY_0", "credential.helper"),
+        ("GIT_CONFIG_VALUE_0", ""),
+        ("GIT_CONFIG_KEY_1", "credential.helper"),
+        ("GIT_CONFIG_VALUE_1", helper),
+        ("MIRROR_GIT_USERNAME", username),
+        ("MIRROR_GIT_PASSWORD", password),
+    ];
+    run_git(cwd, args, &envs).await
+}
 
-        Ok(())
+async fn run_git(cwd: &Path, args: &[&str], envs: &[(&str, &str)]) -> Result<String> {
+    let output = Command::new("git")
+        .current_dir(cwd)
+        // Never wait on a prompt for credentials
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .envs(envs.iter().copied())
+        .args(args)
+        .output()
+        .await
+        .context("Failed to execute git command")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Git command '{}$ git {}' failed with exit code {}\nstdout: {}\nstderr: {}",
+            cwd.as_os_str().to_str().unwrap(),
+            args.join(" "),
+            output.status.code().unwrap(),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
     }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Where to clone the repos of a remote to, keeping the ones of different remotes apart, and away
+/// from the GitHub ones
+pub fn workspace(kind: &str, remote: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("github-activity-mirror_{}_{}", kind, remote))
 }
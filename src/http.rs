@@ -0,0 +1,341 @@
+use chrono::{DateTime, Utc};
+use octocrab::Octocrab;
+use reqwest::{header::HeaderMap, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::time::Duration;
+use url::Url;
+
+const MAX_ATTEMPTS: u32 = 5;
+// Longest a rate limit is waited out. GitHub resets its limits every hour
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// Send a request, retrying transient errors with an exponential backoff, and waiting out rate
+/// limits. Only idempotent requests are retried on errors, the rest are only retried when they
+/// were rejected by a rate limit, as they were never processed then.
+pub async fn send(request: RequestBuilder) -> anyhow::Result<Response> {
+    let idempotent = request
+        .try_clone()
+        .and_then(|r| r.build().ok())
+        .is_some_and(|r| r.method().is_idempotent());
+
+    let mut attempt = 1;
+    loop {
+        // Requests with a streaming body can't be sent more than once
+        let Some(this_attempt) = request.try_clone() else {
+            return Ok(request.send().await?);
+        };
+        let last_attempt = attempt >= MAX_ATTEMPTS;
+
+        let wait = match this_attempt.send().await {
+            Ok(response) => {
+                if let Some(wait) = rate_limit_wait(response.status(), response.headers()) {
+                    if last_attempt {
+                        return Ok(response);
+                    }
+                    wait.unwrap_or_else(|| backoff(attempt))
+                } else if idempotent && response.status().is_server_error() && !last_attempt {
+                    backoff(attempt)
+                } else {
+                    // Don't run into the limit on the next request
+                    if let Some(wait) = exhausted_wait(response.headers()) {
+                        let host = response.url().host_str().unwrap_or_default();
+                        wait_for(wait, &format!("No requests left to {}", host)).await;
+                    }
+                    return Ok(response);
+                }
+            }
+            Err(e) if idempotent && is_transient(&e) && !last_attempt => backoff(attempt),
+            Err(e) => return Err(e.into()),
+        };
+
+        let host = request
+            .try_clone()
+            .and_then(|r| r.build().ok())
+            .and_then(|r| r.url().host_str().map(str::to_string))
+            .unwrap_or_default();
+        wait_for(wait, &format!("Request to {} failed", host)).await;
+        attempt += 1;
+    }
+}
+
+/// URL made out of the base URL and the segments, which are escaped. The segments go under the
+/// base URL whether it ends with a slash or not
+pub fn join(base: &Url, segments: &[&str]) -> Url {
+    let mut url = base.clone();
+    url.path_segments_mut()
+        .expect("Base URLs are http(s) URLs")
+        .pop_if_empty()
+        .extend(segments);
+    url
+}
+
+/// Send a GET request, where a 404 means that what was asked for doesn't exist
+pub async fn get_found(request: RequestBuilder) -> anyhow::Result<Option<Response>> {
+    let response = send(request).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(response.error_for_status()?))
+}
+
+/// Same as `get_found`, reading the response as JSON
+pub async fn get_json<T: DeserializeOwned>(request: RequestBuilder) -> anyhow::Result<Option<T>> {
+    match get_found(request).await? {
+        Some(response) => Ok(Some(response.json().await?)),
+        None => Ok(None),
+    }
+}
+
+/// A page of a listing, linking to the next one
+pub trait LinkedPage {
+    type Item;
+
+    fn into_parts(self) -> (Vec<Self::Item>, Option<Url>);
+}
+
+/// Every item of a listing, following the links from one page to the next. The pages are
+/// fetched with `get`, so each client can add its authentication
+pub async fn get_linked<P, F, Fut>(url: Url, get: F) -> anyhow::Result<Vec<P::Item>>
+where
+    P: LinkedPage,
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = anyhow::Result<Option<P>>>,
+{
+    let mut items = vec![];
+    let mut next = Some(url);
+    while let Some(url) = next {
+        let Some(page) = get(url).await? else {
+            break;
+        };
+        let (page_items, next_url) = page.into_parts();
+        items.extend(page_items);
+        next = next_url;
+    }
+    Ok(items)
+}
+
+/// Same as `send`, but for requests through octocrab. As the response headers aren't available,
+/// the rate limit API is asked when the limit resets instead.
+pub async fn github<T, F, Fut>(
+    octocrab: &Octocrab,
+    idempotent: bool,
+    request: F,
+) -> octocrab::Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = octocrab::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let e = match request().await {
+            Ok(result) => return Ok(result),
+            Err(e) if attempt >= MAX_ATTEMPTS => return Err(e),
+            Err(e) => e,
+        };
+
+        let wait = match GithubLimit::of(&e) {
+            Some(GithubLimit::Primary) => match octocrab.ratelimit().get().await {
+                Ok(limit) => until(limit.resources.core.reset).unwrap_or_else(|| backoff(attempt)),
+                Err(_) => backoff(attempt),
+            },
+            // GitHub asks to wait at least a minute after hitting a secondary rate limit
+            Some(GithubLimit::Secondary) => Duration::from_secs(60) * attempt,
+            None if idempotent && is_transient_github(&e) => backoff(attempt),
+            None => return Err(e),
+        };
+        wait_for(wait, &format!("Request to GitHub failed ({})", e)).await;
+        attempt += 1;
+    }
+}
+
+pub enum GithubLimit {
+    /// The hourly API limit
+    Primary,
+    /// The abuse detection limits, mostly hit when creating a lot of content quickly
+    Secondary,
+}
+
+impl GithubLimit {
+    pub fn of(e: &octocrab::Error) -> Option<Self> {
+        let octocrab::Error::GitHub { source, .. } = e else {
+            return None;
+        };
+        if source.status_code != StatusCode::FORBIDDEN
+            && source.status_code != StatusCode::TOO_MANY_REQUESTS
+        {
+            return None;
+        }
+
+        let message = source.message.to_lowercase();
+        if message.contains("secondary rate limit") || message.contains("abuse") {
+            Some(GithubLimit::Secondary)
+        } else if message.contains("rate limit") {
+            Some(GithubLimit::Primary)
+        } else {
+            None
+        }
+    }
+}
+
+/// How long to wait when rate limited. The outer `None` means it wasn't rate limited, the inner
+/// `None` that it was, but the server didn't say for how long.
+fn rate_limit_wait(status: StatusCode, headers: &HeaderMap) -> Option<Option<Duration>> {
+    if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::FORBIDDEN {
+        return None;
+    }
+
+    if let Some(retry_after) = header(headers, "Retry-After") {
+        if let Ok(seconds) = retry_after.parse::<u64>() {
+            return Some(Some(Duration::from_secs(seconds)));
+        }
+        if let Ok(date) = DateTime::parse_from_rfc2822(retry_after) {
+            return Some(until(date.timestamp() as u64));
+        }
+    }
+    if let Some(wait) = exhausted_wait(headers) {
+        return Some(Some(wait));
+    }
+
+    // A 403 without any rate limit headers is just a 403
+    (status == StatusCode::TOO_MANY_REQUESTS).then_some(None)
+}
+
+/// How long until the rate limit resets, if there are no requests left
+fn exhausted_wait(headers: &HeaderMap) -> Option<Duration> {
+    if header(headers, "X-RateLimit-Remaining")? != "0" {
+        return None;
+    }
+    header(headers, "X-RateLimit-Reset")?
+        .parse::<u64>()
+        .ok()
+        .and_then(until)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Time left until the unix timestamp, with a second of margin
+fn until(timestamp: u64) -> Option<Duration> {
+    let seconds = timestamp.checked_sub(Utc::now().timestamp() as u64)?;
+    Some(Duration::from_secs(seconds + 1))
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.pow(attempt - 1))
+}
+
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.is_request()
+}
+
+fn is_transient_github(e: &octocrab::Error) -> bool {
+    match e {
+        octocrab::Error::GitHub { source, .. } => source.status_code.is_server_error(),
+        octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. } => true,
+        _ => false,
+    }
+}
+
+async fn wait_for(wait: Duration, reason: &str) {
+    let wait = wait.min(MAX_WAIT);
+    eprintln!("{}, waiting {} seconds", reason, wait.as_secs());
+    tokio::time::sleep(wait).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    /// The wait is rounded to seconds and gets a second of margin, so it is only checked roughly
+    fn assert_about(wait: Option<Option<Duration>>, seconds: u64) {
+        let wait = wait.flatten().unwrap().as_secs();
+        assert!(
+            (seconds - 1..=seconds + 2).contains(&wait),
+            "waiting {} seconds rather than {}",
+            wait,
+            seconds
+        );
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        let headers = headers(&[("Retry-After", "30".to_string())]);
+        assert_eq!(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &headers),
+            Some(Some(Duration::from_secs(30)))
+        );
+    }
+
+    #[test]
+    fn retry_after_as_http_date() {
+        let date = Utc::now() + chrono::Duration::seconds(120);
+        let headers = headers(&[(
+            "Retry-After",
+            date.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        )]);
+        assert_about(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &headers),
+            120,
+        );
+
+        // A date that already passed doesn't say how long to wait
+        let headers =
+            self::headers(&[("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT".to_string())]);
+        assert_eq!(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &headers),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn rate_limit_reset_when_exhausted() {
+        let reset = (Utc::now().timestamp() + 60).to_string();
+        let exhausted = headers(&[
+            ("X-RateLimit-Remaining", "0".to_string()),
+            ("X-RateLimit-Reset", reset.clone()),
+        ]);
+        assert_about(rate_limit_wait(StatusCode::FORBIDDEN, &exhausted), 60);
+        assert_about(Some(exhausted_wait(&exhausted)), 60);
+
+        let remaining = headers(&[
+            ("X-RateLimit-Remaining", "10".to_string()),
+            ("X-RateLimit-Reset", reset),
+        ]);
+        assert_eq!(exhausted_wait(&remaining), None);
+        // A 403 with requests left is a real 403
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &remaining), None);
+    }
+
+    #[test]
+    fn only_rate_limit_statuses_wait() {
+        assert_eq!(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new()),
+            Some(None)
+        );
+        assert_eq!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &HeaderMap::new()),
+            None
+        );
+        let headers = headers(&[("Retry-After", "30".to_string())]);
+        assert_eq!(
+            rate_limit_wait(StatusCode::SERVICE_UNAVAILABLE, &headers),
+            None
+        );
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let waits: Vec<u64> = (1..=MAX_ATTEMPTS).map(|a| backoff(a).as_secs()).collect();
+        assert_eq!(waits, [1, 2, 4, 8, 16]);
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::activity;
+
+/// What a source activity became on the mirror
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mirrored {
+    Commit { sha: String },
+    Issue { number: u64 },
+    // Was already on the mirror before the ledger started tracking the repo
+    Legacy,
+}
+
+/// If an activity was on the mirror before the ledger started tracking its repo, which is when it
+/// is older than the last thing mirrored. `last_mirrored` is only given for untracked repos
+pub fn is_legacy(
+    last_mirrored: Option<DateTime<FixedOffset>>,
+    date: DateTime<FixedOffset>,
+) -> bool {
+    last_mirrored.is_some_and(|last_mirrored| last_mirrored > date)
+}
+
+/// Entries of a single target, grouped by the source repository URL, and keyed by `Activity::key`
+type TargetEntries = HashMap<String, HashMap<String, Mirrored>>;
+
+/// Local record of every activity that has been mirrored, so syncing doesn't depend on dates
+///
+/// Entries are grouped by the target they are mirrored to, as identified by `MirrorTarget::id`.
+#[derive(Deserialize, Serialize, Default)]
+pub struct Ledger {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    targets: HashMap<String, TargetEntries>,
+    /// Entries from before there could be more than one target, which were all for GitHub
+    #[serde(default, skip_serializing)]
+    repos: TargetEntries,
+}
+
+impl Ledger {
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                ..Default::default()
+            });
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read ledger {}", path.display()))?;
+        let mut ledger: Ledger = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse ledger {}", path.display()))?;
+        ledger.path = path;
+
+        Ok(ledger)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        // Write to a temporary file first, so an interrupted run can't leave a truncated ledger
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write ledger {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write ledger {}", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Move the entries from before there could be more than one target over to the target
+    pub fn adopt_untargeted(&mut self, target: &str) {
+        let repos = std::mem::take(&mut self.repos);
+        let entries = self.targets.entry(target.to_string()).or_default();
+        for (repo, keys) in repos {
+            entries.entry(repo).or_default().extend(keys);
+        }
+    }
+
+    /// If the repo has been synced to the target while the ledger was in use
+    pub fn is_tracked(&self, target: &str, repo: &activity::Repository) -> bool {
+        self.targets
+            .get(target)
+            .is_some_and(|entries| entries.contains_key(repo.html_url.as_str()))
+    }
+
+    /// Start tracking a repo, even if nothing has been mirrored to it yet
+    pub fn track(&mut self, target: &str, repo: &activity::Repository) {
+        self.targets
+            .entry(target.to_string())
+            .or_default()
+            .entry(repo.html_url.to_string())
+            .or_default();
+    }
+
+    pub fn get(&self, target: &str, repo: &activity::Repository, key: &str) -> Option<&Mirrored> {
+        self.targets
+            .get(target)
+            .and_then(|entries| entries.get(repo.html_url.as_str()))
+            .and_then(|entries| entries.get(key))
+    }
+
+    pub fn insert(
+        &mut self,
+        target: &str,
+        repo: &activity::Repository,
+        key: String,
+        mirrored: Mirrored,
+    ) {
+        self.targets
+            .entry(target.to_string())
+            .or_default()
+            .entry(repo.html_url.to_string())
+            .or_default()
+            .insert(key, mirrored);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn repo(html_url: &str) -> activity::Repository {
+        let html_url = Url::parse(html_url).unwrap();
+        activity::Repository {
+            owned_by_you: true,
+            owner: "me".to_string(),
+            name: "repo".to_string(),
+            description: None,
+            full_name: "me/repo".to_string(),
+            clone_url: html_url.clone(),
+            html_url,
+            private: false,
+            created_date: date("2024-01-01T00:00:00Z"),
+        }
+    }
+
+    fn date(date: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(date).unwrap()
+    }
+
+    /// A ledger file of its own for each test, as they run at the same time
+    fn ledger_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ledger-{}-{}.json", test, std::process::id()))
+    }
+
+    #[test]
+    fn entries_survive_a_save_and_load() {
+        let path = ledger_path("round-trip");
+        let repo = repo("https://gitea.example.com/me/repo");
+        let mut ledger = Ledger::load(path.clone()).unwrap();
+        ledger.insert(
+            "github",
+            &repo,
+            "commit/abc".to_string(),
+            Mirrored::Commit {
+                sha: "def".to_string(),
+            },
+        );
+        ledger.insert(
+            "github",
+            &repo,
+            "issue/1".to_string(),
+            Mirrored::Issue { number: 7 },
+        );
+        ledger.insert("github", &repo, "issue/2".to_string(), Mirrored::Legacy);
+        ledger.save().unwrap();
+
+        let loaded = Ledger::load(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            loaded.get("github", &repo, "commit/abc"),
+            Some(Mirrored::Commit { sha }) if sha == "def"
+        ));
+        assert!(matches!(
+            loaded.get("github", &repo, "issue/1"),
+            Some(Mirrored::Issue { number: 7 })
+        ));
+        assert!(matches!(
+            loaded.get("github", &repo, "issue/2"),
+            Some(Mirrored::Legacy)
+        ));
+    }
+
+    #[test]
+    fn missing_ledgers_start_empty() {
+        let ledger = Ledger::load(ledger_path("missing")).unwrap();
+        assert!(!ledger.is_tracked("github", &repo("https://gitea.example.com/me/repo")));
+    }
+
+    #[test]
+    fn entries_are_per_target_and_repo() {
+        let repo = repo("https://gitea.example.com/me/repo");
+        let other = self::repo("https://gitea.example.com/me/other");
+        let mut ledger = Ledger::default();
+        ledger.insert("github", &repo, "commit/abc".to_string(), Mirrored::Legacy);
+
+        assert!(ledger.get("github", &repo, "commit/abc").is_some());
+        assert!(ledger.get("github", &repo, "commit/def").is_none());
+        assert!(ledger.get("github", &other, "commit/abc").is_none());
+        assert!(ledger.get("gitea", &repo, "commit/abc").is_none());
+        assert!(ledger.is_tracked("github", &repo));
+        assert!(!ledger.is_tracked("gitea", &repo));
+    }
+
+    #[test]
+    fn tracked_repos_have_no_entries_yet() {
+        let repo = repo("https://gitea.example.com/me/repo");
+        let mut ledger = Ledger::default();
+        ledger.track("github", &repo);
+        assert!(ledger.is_tracked("github", &repo));
+        assert!(ledger.get("github", &repo, "commit/abc").is_none());
+    }
+
+    #[test]
+    fn untargeted_entries_go_to_the_target() {
+        let mut ledger: Ledger = serde_json::from_str(
+            r#"{"repos": {"https://gitea.example.com/me/repo": {"issue/1": {"kind": "legacy"}}}}"#,
+        )
+        .unwrap();
+        ledger.adopt_untargeted("github");
+
+        let repo = repo("https://gitea.example.com/me/repo");
+        assert!(matches!(
+            ledger.get("github", &repo, "issue/1"),
+            Some(Mirrored::Legacy)
+        ));
+        assert!(ledger.repos.is_empty());
+    }
+
+    #[test]
+    fn activities_older_than_the_mirror_are_legacy() {
+        let last_mirrored = Some(date("2024-06-01T12:00:00Z"));
+        assert!(is_legacy(last_mirrored, date("2024-05-31T12:00:00Z")));
+        assert!(!is_legacy(last_mirrored, date("2024-06-01T12:00:00Z")));
+        assert!(!is_legacy(last_mirrored, date("2024-06-02T12:00:00Z")));
+        // Tracked and new repos have nothing from before the ledger
+        assert!(!is_legacy(None, date("2020-01-01T00:00:00Z")));
+    }
+}
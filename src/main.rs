@@ -1,18 +1,32 @@
-use anyhow;
 use clap::Parser;
+use futures::stream::{self, StreamExt};
 use std::{
     collections::{HashMap, HashSet},
     path::PathBuf,
+    process::ExitCode,
+    sync::{Arc, Mutex},
 };
-use tokio;
 
 use config::Config;
+use error::Error;
+use ledger::Ledger;
+use mirror::Mirrors;
+use report::{Failed, Report};
+use services::ServiceConfig;
 
 mod activity;
 mod config;
+mod daemon;
+mod error;
 mod git;
-mod github;
+mod http;
+mod ledger;
+mod mirror;
+mod plan;
+mod report;
 mod services;
+mod targets;
+mod webhook;
 
 /// Application to mirror GitHub activity from other git platforms
 #[derive(Parser)]
@@ -21,28 +35,159 @@ struct Cli {
     /// Path to settings.toml config file
     #[clap(name = "PATH", default_value = "./settings.toml")]
     path: PathBuf,
+
+    /// Fetch everything and print what would be changed, without touching the targets
+    #[clap(long, conflicts_with_all = ["daemon", "webhook"])]
+    dry_run: bool,
+
+    /// Keep running, syncing on the interval set in the config file
+    #[clap(long)]
+    daemon: bool,
+
+    /// Keep running, syncing whenever a webhook is received
+    #[clap(long)]
+    webhook: bool,
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
-    let config = Config::load(cli.path)?;
+    match run(cli).await {
+        Ok(Some(report)) => {
+            println!("\n{}", report);
+            report.exit_code()
+        }
+        // The daemon and webhooks report each of their syncs
+        Ok(None) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    }
+}
+
+/// Everything that fails here is fatal, failures of single services and repos end up in the report
+async fn run(cli: Cli) -> Result<Option<Report>, Error> {
+    let mut config = Config::load(cli.path).map_err(Error::Config)?;
+    let check_report = check_services(&mut config).await;
+
+    let ledger = Mutex::new(Ledger::load(config.ledger.clone()).map_err(Error::Ledger)?);
+    let mirrors = Mirrors::connect(&config, &ledger).await?;
+
+    if cli.daemon || cli.webhook {
+        let config = Arc::new(config);
+        let mirrors = Arc::new(mirrors);
+        let ledger = Arc::new(ledger);
+        tokio::try_join!(
+            async {
+                if !cli.daemon {
+                    return Ok(());
+                }
+                daemon::run(config.clone(), mirrors.clone(), ledger.clone()).await
+            },
+            async {
+                if !cli.webhook {
+                    return Ok(());
+                }
+                webhook::serve(config.clone(), mirrors.clone(), ledger.clone()).await
+            },
+        )?;
+        return Ok(None);
+    }
+
+    let (repos, mut report) = fetch_repos(&config).await;
+    report.extend(check_report);
+
+    if cli.dry_run {
+        let (plans, plan_report) = mirrors.plan(repos, &ledger).await;
+        plan::print_plans(&plans);
+        report.extend(plan_report);
+        return Ok(Some(report));
+    }
+    report.extend(mirrors.sync(repos, &ledger).await);
+
+    Ok(Some(report))
+}
+
+/// Make sure all the services can be fetched from, before running into it later. Services that
+/// can't are left out for the rest of the run, and reported
+async fn check_services(config: &mut Config) -> Report {
+    let mut report = Report::default();
+
+    let results: Vec<_> = stream::iter(&config.services)
+        .map(check_service)
+        .buffered(config.concurrency)
+        .collect()
+        .await;
+    for (service, result) in config.services.iter_mut().zip(results) {
+        if let Err(failed) = result {
+            service.client = None;
+            report.failed.push(failed);
+        }
+    }
 
+    report
+}
+async fn check_service(service: &ServiceConfig) -> Result<(), Failed> {
+    let Some(client) = &service.client else {
+        return Ok(());
+    };
+
+    client.check_version().await.map_err(|e| {
+        let name = format!("{:?} ({})", service.service_type, service.url);
+        let error = Error::source_api(e);
+        eprintln!("Unable to use {}, leaving it out: {}", name, error);
+        Failed { name, error }
+    })
+}
+
+/// Fetch the activities of all the services. Services that fail are left out, and reported
+pub async fn fetch_repos(
+    config: &Config,
+) -> (
+    HashMap<activity::Repository, HashSet<activity::Activity>>,
+    Report,
+) {
     let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+    let mut report = Report::default();
 
-    for service in config.services {
-        if let Some(client) = &service.client {
-            println!(
-                "\nFetching activities from {:?} ({})",
-                service.service_type, service.url
-            );
-            let result = client.get_repos().await?;
-            repos.extend(result);
+    let results: Vec<_> = stream::iter(&config.services)
+        .map(fetch_service)
+        .buffer_unordered(config.concurrency)
+        .collect()
+        .await;
+    for result in results {
+        match result {
+            Ok(service_repos) => repos.extend(service_repos),
+            Err(failed) => report.failed.push(failed),
         }
     }
 
-    let github_client = github::GithubClient::new(config.github).await;
-    github_client.sync(repos).await?;
+    (repos, report)
+}
+async fn fetch_service(
+    service: &ServiceConfig,
+) -> Result<HashMap<activity::Repository, HashSet<activity::Activity>>, Failed> {
+    let Some(client) = &service.client else {
+        return Ok(HashMap::new());
+    };
+
+    println!(
+        "Fetching activities from {:?} ({})",
+        service.service_type, service.url
+    );
+    let repos = client.get_repos().await.map_err(|e| {
+        let name = format!("{:?} ({})", service.service_type, service.url);
+        let error = Error::source_api(e);
+        eprintln!("Failed to fetch {}: {}", name, error);
+        Failed { name, error }
+    })?;
+    println!(
+        "Fetched {} repos from {:?} ({})",
+        repos.len(),
+        service.service_type,
+        service.url
+    );
 
-    Ok(())
+    Ok(repos)
 }
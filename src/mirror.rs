@@ -0,0 +1,638 @@
+use chrono::DateTime;
+use sha1_smol::Sha1;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::stream::{self, StreamExt};
+
+use crate::{
+    activity::{self, ActivityContent},
+    config::{Config, RedactLevel},
+    error::Error,
+    ledger::{is_legacy, Ledger, Mirrored},
+    plan::{Change, RepoPlan},
+    report::{Report, Synced},
+    targets::{MirrorRepo, MirrorTarget, TargetType},
+};
+
+/// Syncs activities to a single target
+pub struct Mirror {
+    target: Box<dyn MirrorTarget>,
+    /// Identifies the target in the ledger
+    id: String,
+    /// How many mirror repos to sync at the same time
+    concurrency: usize,
+    /// Set when shutting down, so no new repos are started
+    stopping: AtomicBool,
+    mirror_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl Mirror {
+    pub fn new(target: Box<dyn MirrorTarget>, concurrency: usize) -> Self {
+        Self {
+            id: target.id(),
+            target,
+            concurrency,
+            stopping: AtomicBool::new(false),
+            mirror_locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sync all the activities to the target
+    ///
+    /// Mirror repos are synced concurrently, while source repos sharing the same mirror are synced
+    /// one after another, so they never touch the same git workspace at the same time. A repo
+    /// failing to sync doesn't stop the others.
+    pub async fn sync(
+        &self,
+        repos: HashMap<activity::Repository, HashSet<activity::Activity>>,
+        ledger: &Mutex<Ledger>,
+    ) -> Report {
+        let reports: Vec<Report> = stream::iter(self.group_by_mirror(repos))
+            .map(|(name, group)| async move {
+                let mut report = Report::default();
+                let _workspace = self.lock_mirror(&name).await;
+                for (source_repo, activities) in group {
+                    if self.stopping.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let source_name = source_repo.html_url.to_string();
+                    match self.sync_repo(source_repo, activities, ledger).await {
+                        Ok(Some(synced)) => report.synced.push(synced),
+                        Ok(None) => {}
+                        Err(e) => {
+                            eprintln!("Failed to sync {} to {}: {}", source_name, self.id, e);
+                            report.fail(source_name, e);
+                        }
+                    }
+                }
+                report
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut report = Report::default();
+        for r in reports {
+            report.extend(r);
+        }
+        report
+    }
+
+    async fn sync_repo(
+        &self,
+        source_repo: activity::Repository,
+        activities: HashSet<activity::Activity>,
+        ledger: &Mutex<Ledger>,
+    ) -> Result<Option<Synced>, Error> {
+        let Some(plan) = self.plan_repo(source_repo, activities, ledger).await? else {
+            return Ok(None);
+        };
+        // Saved even when applying fails, so issues that were already created aren't created again
+        let synced = self.apply_repo(plan, ledger).await;
+        ledger.lock().unwrap().save().map_err(Error::Ledger)?;
+        synced.map(Some)
+    }
+
+    /// Let the repos being synced finish, but don't start any new ones
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Relaxed);
+    }
+
+    /// Work out what a sync would do, without changing anything on the target
+    ///
+    /// Planning clones and pulls the mirror repos too, so it goes through the mirrors the same way
+    /// as `sync` does.
+    pub async fn plan(
+        &self,
+        repos: HashMap<activity::Repository, HashSet<activity::Activity>>,
+        ledger: &Mutex<Ledger>,
+    ) -> (Vec<RepoPlan>, Report) {
+        let results: Vec<(Vec<RepoPlan>, Report)> = stream::iter(self.group_by_mirror(repos))
+            .map(|(name, group)| async move {
+                let mut plans = vec![];
+                let mut report = Report::default();
+                let _workspace = self.lock_mirror(&name).await;
+                for (source_repo, activities) in group {
+                    let source_name = source_repo.html_url.to_string();
+                    match self.plan_repo(source_repo, activities, ledger).await {
+                        Ok(Some(plan)) => {
+                            // Repos that are already up to date aren't reported as planned
+                            if !plan.changes.is_empty() || plan.repo.is_none() {
+                                report.planned.push(Synced {
+                                    name: plan.full_name.clone(),
+                                    commits: plan.commit_count(),
+                                    issues: plan.issue_count(),
+                                });
+                            }
+                            plans.push(plan);
+                        }
+                        Ok(None) => {}
+                        Err(e) => report.fail(source_name, e),
+                    }
+                }
+                (plans, report)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut plans = vec![];
+        let mut report = Report::default();
+        for (group_plans, group_report) in results {
+            plans.extend(group_plans);
+            report.extend(group_report);
+        }
+        plans.sort_by(|a, b| a.full_name.cmp(&b.full_name));
+
+        (plans, report)
+    }
+
+    /// Group the source repos by the name of the mirror they end up in
+    fn group_by_mirror(
+        &self,
+        repos: HashMap<activity::Repository, HashSet<activity::Activity>>,
+    ) -> HashMap<String, Vec<(activity::Repository, HashSet<activity::Activity>)>> {
+        let mut groups: HashMap<String, Vec<_>> = HashMap::new();
+        for (source_repo, activities) in repos {
+            groups
+                .entry(self.mirror_name(&source_repo).to_lowercase())
+                .or_default()
+                .push((source_repo, activities));
+        }
+        groups
+    }
+
+    /// Wait until no one else is syncing to the mirror, as syncs from the daemon and webhooks
+    /// can overlap, and they would share the same git workspace
+    async fn lock_mirror(&self, name: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .mirror_locks
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    async fn plan_repo(
+        &self,
+        source_repo: activity::Repository,
+        activities: HashSet<activity::Activity>,
+        ledger: &Mutex<Ledger>,
+    ) -> Result<Option<RepoPlan>, Error> {
+        let config = self.target.config();
+        let name = self.mirror_name(&source_repo);
+
+        println!("Checking {}/{} on {}", config.username, name, self.id);
+        let repo = match self.target.find_repo(&name).await? {
+            Some(repo) => Some(repo),
+            None => {
+                let hashed_name = Sha1::from(&name).digest().to_string();
+                println!(
+                    "Checking {}/{} ({}/{}) on {}",
+                    config.username, hashed_name, config.username, name, self.id
+                );
+                self.target.find_repo(&hashed_name).await?
+            }
+        };
+
+        if let Some(repo) = &repo {
+            if !self.target.is_mirror(repo).await? {
+                return Ok(None);
+            }
+        }
+
+        // Anything older than what is already on the mirror has been synced before. This is only
+        // used the first time the ledger sees a repo, as squashing/force push will make this
+        // unreliable
+        let tracked = ledger.lock().unwrap().is_tracked(&self.id, &source_repo);
+        let last_activity = match &repo {
+            Some(repo) if !tracked => {
+                let last_commit = self
+                    .target
+                    .git()
+                    .last_commit(repo)
+                    .await
+                    .map_err(Error::Git)?;
+                let last_issue = self.target.last_issue_date(repo).await?;
+                Some(match last_issue {
+                    Some(last_issue) => max(last_issue, last_commit.timestamp),
+                    None => last_commit.timestamp,
+                })
+            }
+            _ => None,
+        };
+
+        let mut existing_issues: Option<Vec<String>> = None;
+        let mut activities = activities.into_iter().collect::<Vec<activity::Activity>>();
+        let mut changes = vec![];
+        let mut legacy = vec![];
+
+        // Sort date in ascending order
+        activities.sort_by_key(|a| a.date);
+
+        for activity in activities {
+            let key = activity.key();
+            if ledger
+                .lock()
+                .unwrap()
+                .get(&self.id, &source_repo, &key)
+                .is_some()
+            {
+                continue;
+            }
+            if is_legacy(last_activity, activity.date) {
+                legacy.push(key);
+                continue;
+            }
+
+            match &activity.content {
+                ActivityContent::Commit(c) => {
+                    let (message, content) = self.render_commit(&activity, c);
+                    changes.push(Change::Commit {
+                        key,
+                        date: activity.date,
+                        message,
+                        content,
+                        summary: format!(
+                            "{}{}",
+                            c.message
+                                .lines()
+                                .find(|line| !line.trim().is_empty())
+                                .unwrap_or("<Empty commit message>"),
+                            if c.message.lines().count() > 1 {
+                                " ..."
+                            } else {
+                                ""
+                            }
+                        ),
+                    });
+                }
+                ActivityContent::Issue(i) if activity.is_opening() => {
+                    let (title, body) = self.render_issue(&activity, i);
+
+                    let existing_issues = match (&mut existing_issues, &repo) {
+                        (Some(existing_issues), _) => existing_issues,
+                        (None, Some(repo)) => existing_issues.insert(
+                            self.target
+                                .issue_titles_since(repo, last_activity.unwrap_or(activity.date))
+                                .await?,
+                        ),
+                        (None, None) => existing_issues.insert(vec![]),
+                    };
+
+                    if existing_issues.contains(&title) {
+                        legacy.push(key);
+                        continue;
+                    }
+                    existing_issues.push(title.clone());
+                    changes.push(Change::Issue {
+                        key,
+                        date: activity.date,
+                        title,
+                        body,
+                        summary: format!(
+                            "[{}] {}",
+                            i.issue_id,
+                            if i.message.is_empty() {
+                                "<Empty commit message>"
+                            } else {
+                                &i.message
+                            }
+                        ),
+                    });
+                }
+                // Merges, reviews, comments and the like show up as commits
+                ActivityContent::Issue(_)
+                | ActivityContent::Comment(_)
+                | ActivityContent::PatchSet(_) => {
+                    let description = activity.describe();
+                    let (message, content) = self.render_event(&activity, &description);
+                    changes.push(Change::Commit {
+                        key,
+                        date: activity.date,
+                        message,
+                        content,
+                        summary: description,
+                    });
+                }
+            }
+        }
+
+        let full_name = match &repo {
+            Some(repo) => repo.full_name.clone(),
+            None => format!("{}/{}", config.username, self.redacted_name(&name)),
+        };
+        let description = match config.redact_level {
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => source_repo
+                .description
+                .as_ref()
+                .map(|value| Sha1::from(value).digest().to_string()),
+            _ => source_repo.description.clone(),
+        };
+
+        Ok(Some(RepoPlan {
+            source_repo,
+            target: self.id.clone(),
+            full_name,
+            description,
+            private: config.redact_level != RedactLevel::Off,
+            repo,
+            changes,
+            legacy,
+        }))
+    }
+
+    async fn apply_repo(&self, plan: RepoPlan, ledger: &Mutex<Ledger>) -> Result<Synced, Error> {
+        let source_repo = &plan.source_repo;
+        let mut synced = Synced {
+            name: plan.full_name.clone(),
+            commits: plan.commit_count(),
+            issues: plan.issue_count(),
+        };
+        {
+            let mut ledger = ledger.lock().unwrap();
+            ledger.track(&self.id, source_repo);
+            for key in plan.legacy.iter() {
+                ledger.insert(&self.id, source_repo, key.clone(), Mirrored::Legacy);
+            }
+        }
+
+        let repo = match &plan.repo {
+            Some(repo) => repo.clone(),
+            None => {
+                let Some(first_change) = plan.changes.first() else {
+                    return Ok(synced);
+                };
+                println!("Creating repo: {}", source_repo.full_name);
+                self.create_repo(&plan, first_change.date()).await?
+            }
+        };
+
+        // Commits are only recorded in the ledger once they are pushed
+        let mut pending_commits: Vec<(String, String)> = vec![];
+
+        if plan.changes.is_empty() {
+            return Ok(synced);
+        }
+        synced.name = repo.full_name.clone();
+        println!("Syncing: {}", synced.name);
+        self.target
+            .git()
+            .initialize_local_git(&repo)
+            .await
+            .map_err(Error::Git)?;
+
+        for change in plan.changes {
+            match change {
+                Change::Commit {
+                    key,
+                    date,
+                    message,
+                    content,
+                    summary,
+                } => {
+                    let sha = self
+                        .target
+                        .add_commit(&repo, message, content, date)
+                        .await?;
+                    pending_commits.push((key, sha));
+                    println!("{} - {}: {}", date, repo.full_name, summary);
+                }
+                Change::Issue {
+                    key,
+                    date,
+                    title,
+                    body,
+                    summary,
+                } => {
+                    println!("{} - {}: {}", date, repo.full_name, summary);
+                    let number = self.target.create_issue(&repo, &title, &body, date).await?;
+                    ledger.lock().unwrap().insert(
+                        &self.id,
+                        source_repo,
+                        key,
+                        Mirrored::Issue { number },
+                    );
+                }
+            }
+        }
+
+        let pushed_commits = self.target.push(&repo).await?;
+        if pushed_commits > 0 {
+            println!("Pushed {} new commits to {}", pushed_commits, repo.html_url);
+        }
+        let mut ledger = ledger.lock().unwrap();
+        for (key, sha) in pending_commits {
+            ledger.insert(&self.id, source_repo, key, Mirrored::Commit { sha });
+        }
+
+        Ok(synced)
+    }
+
+    async fn create_repo(
+        &self,
+        plan: &RepoPlan,
+        init_date: DateTime<chrono::FixedOffset>,
+    ) -> Result<MirrorRepo, Error> {
+        let name = plan
+            .full_name
+            .split_once('/')
+            .map_or(plan.full_name.as_str(), |(_, name)| name);
+
+        let repo = self
+            .target
+            .create_repo(name, plan.description.as_deref(), plan.private)
+            .await?;
+        self.target
+            .git()
+            .create_init(&repo, init_date)
+            .await
+            .map_err(Error::Git)?;
+
+        println!("Created repo: {}", repo.html_url);
+
+        Ok(repo)
+    }
+
+    /// Name of the mirror repo, before redaction
+    fn mirror_name(&self, source_repo: &activity::Repository) -> String {
+        if source_repo.owned_by_you {
+            source_repo.name.clone()
+        } else {
+            format!("{}-{}", source_repo.owner, source_repo.name)
+        }
+    }
+
+    fn redacted_name(&self, name: &str) -> String {
+        match self.target.config().redact_level {
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => Sha1::from(name).digest().to_string(),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Commit message and README content of a mirrored commit
+    fn render_commit(
+        &self,
+        activity: &activity::Activity,
+        c: &activity::Commit,
+    ) -> (String, String) {
+        let redact_level = &self.target.config().redact_level;
+        let commit_message: String = match redact_level {
+            RedactLevel::PrivateReposNoCrossLinking => c.message.clone(),
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => Sha1::from(&c.message).digest().to_string(),
+            _ => format!("{}\n\nMirrored from: {}", c.message, activity.source_link),
+        };
+        let commit_content = match redact_level {
+            RedactLevel::PrivateReposNoCrossLinking => {
+                format!("{} {}: {}", &c.sha1, c.timestamp, c.message)
+            }
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => {
+                Sha1::from(format!("{} {}: {}", &c.sha1, c.timestamp, c.message))
+                    .digest()
+                    .to_string()
+            }
+            _ => format!(
+                "{} {}: {}\n\n*{}*",
+                &c.sha1, c.timestamp, c.message, activity.source_link
+            ),
+        };
+        (commit_message, commit_content)
+    }
+
+    /// Commit message and README content of a mirrored activity that isn't a commit
+    fn render_event(&self, activity: &activity::Activity, description: &str) -> (String, String) {
+        let redact_level = &self.target.config().redact_level;
+        let commit_message: String = match redact_level {
+            RedactLevel::PrivateReposNoCrossLinking => description.to_string(),
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => Sha1::from(description).digest().to_string(),
+            _ => format!("{}\n\nMirrored from: {}", description, activity.source_link),
+        };
+        let commit_content = match redact_level {
+            RedactLevel::PrivateReposNoCrossLinking => {
+                format!("{}: {}", activity.date, description)
+            }
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => Sha1::from(format!("{}: {}", activity.date, description))
+                .digest()
+                .to_string(),
+            _ => format!(
+                "{}: {}\n\n*{}*",
+                activity.date, description, activity.source_link
+            ),
+        };
+        (commit_message, commit_content)
+    }
+
+    /// Title and body of a mirrored issue
+    fn render_issue(&self, activity: &activity::Activity, i: &activity::Issue) -> (String, String) {
+        let redact_level = &self.target.config().redact_level;
+        let title = match redact_level {
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => Sha1::from(format!("{}: {}", &i.issue_id, &i.message))
+                .digest()
+                .to_string(),
+            _ => format!("[{}] {}", &i.issue_id, &i.message),
+        };
+        let title = if title.len() > 255 {
+            let mut truncated: String = title.chars().take(252).collect();
+            truncated.push_str("...");
+            truncated
+        } else {
+            title
+        };
+        let body = match redact_level {
+            RedactLevel::PrivateReposNoCrossLinking => {
+                format!(
+                    "## Issue ID: {}\n\n{}\n\n{}",
+                    &i.issue_id, &i.message, activity.date
+                )
+            }
+            RedactLevel::Encrypted => encrypted_redaction(),
+            RedactLevel::Hashed => Sha1::from(format!(
+                "## Issue ID: {}\n\n{}\n\n{}",
+                &i.issue_id, &i.message, activity.date
+            ))
+            .digest()
+            .to_string(),
+            _ => format!(
+                "## Issue ID: {}\n\n{}\n\n{}\n\n*{}*",
+                &i.issue_id, &i.message, activity.date, activity.source_link
+            ),
+        };
+        (title, body)
+    }
+}
+
+/// Encrypted redaction can't be configured yet, so it never gets this far
+fn encrypted_redaction() -> ! {
+    unreachable!("Encrypted redaction is rejected at config load")
+}
+
+/// All the targets in the config, synced one after another
+pub struct Mirrors {
+    mirrors: Vec<Mirror>,
+}
+
+impl Mirrors {
+    pub async fn connect(config: &Config, ledger: &Mutex<Ledger>) -> Result<Self, Error> {
+        let mut mirrors = vec![];
+        for target_config in &config.targets {
+            let target = target_config.target_type.connect(target_config).await?;
+            mirrors.push(Mirror::new(target, config.concurrency));
+        }
+
+        // Ledgers from before there could be more than one target only have GitHub entries
+        if let Some(github) = config
+            .targets
+            .iter()
+            .position(|target| target.target_type == TargetType::Github)
+        {
+            ledger.lock().unwrap().adopt_untargeted(&mirrors[github].id);
+        }
+
+        Ok(Self { mirrors })
+    }
+
+    pub async fn sync(
+        &self,
+        repos: HashMap<activity::Repository, HashSet<activity::Activity>>,
+        ledger: &Mutex<Ledger>,
+    ) -> Report {
+        let mut report = Report::default();
+        for mirror in &self.mirrors {
+            report.extend(mirror.sync(repos.clone(), ledger).await);
+        }
+        report
+    }
+
+    pub async fn plan(
+        &self,
+        repos: HashMap<activity::Repository, HashSet<activity::Activity>>,
+        ledger: &Mutex<Ledger>,
+    ) -> (Vec<RepoPlan>, Report) {
+        let mut plans = vec![];
+        let mut report = Report::default();
+        for mirror in &self.mirrors {
+            let (mirror_plans, mirror_report) = mirror.plan(repos.clone(), ledger).await;
+            plans.extend(mirror_plans);
+            report.extend(mirror_report);
+        }
+        (plans, report)
+    }
+
+    pub fn stop(&self) {
+        for mirror in &self.mirrors {
+            mirror.stop();
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::DateTime;
+use std::fmt;
+
+use crate::{activity, targets::MirrorRepo};
+
+/// Everything a sync is going to do to a single mirror repo
+pub struct RepoPlan {
+    pub source_repo: activity::Repository,
+    /// Identifies the target the mirror is on
+    pub target: String,
+    /// Full name of the mirror, with the redact level applied
+    pub full_name: String,
+    pub description: Option<String>,
+    pub private: bool,
+    /// `None` when the mirror doesn't exist yet, and has to be created
+    pub repo: Option<MirrorRepo>,
+    pub changes: Vec<Change>,
+    /// Ledger keys of activities that are already on the mirror, but not recorded in the ledger
+    pub legacy: Vec<String>,
+}
+
+pub enum Change {
+    Commit {
+        key: String,
+        date: DateTime<chrono::FixedOffset>,
+        message: String,
+        content: String,
+        summary: String,
+    },
+    Issue {
+        key: String,
+        date: DateTime<chrono::FixedOffset>,
+        title: String,
+        body: String,
+        summary: String,
+    },
+}
+
+impl Change {
+    pub fn date(&self) -> DateTime<chrono::FixedOffset> {
+        match self {
+            Change::Commit { date, .. } | Change::Issue { date, .. } => *date,
+        }
+    }
+}
+
+impl RepoPlan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn commit_count(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|c| matches!(c, Change::Commit { .. }))
+            .count()
+    }
+
+    pub fn issue_count(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|c| matches!(c, Change::Issue { .. }))
+            .count()
+    }
+}
+
+impl fmt::Display for RepoPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.repo.is_none() {
+            writeln!(
+                f,
+                "+ Create {} ({}) on {} from {}",
+                self.full_name,
+                if self.private { "private" } else { "public" },
+                self.target,
+                self.source_repo.html_url
+            )?;
+            if let Some(description) = &self.description {
+                writeln!(f, "    Description: {}", description)?;
+            }
+        } else {
+            writeln!(
+                f,
+                "~ Sync {} on {} from {}",
+                self.full_name, self.target, self.source_repo.html_url
+            )?;
+        }
+        writeln!(
+            f,
+            "    {} commits, {} issues",
+            self.commit_count(),
+            self.issue_count()
+        )?;
+
+        for change in &self.changes {
+            match change {
+                Change::Commit { date, message, .. } => {
+                    writeln!(f, "    {} commit:", date)?;
+                    for line in message.lines() {
+                        writeln!(f, "        {}", line)?;
+                    }
+                }
+                Change::Issue { date, title, .. } => {
+                    writeln!(f, "    {} issue: {}", date, title)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Print the plans that would change something on the mirror
+pub fn print_plans(plans: &[RepoPlan]) {
+    let plans: Vec<&RepoPlan> = plans.iter().filter(|plan| !plan.is_empty()).collect();
+    println!(
+        "\nPlan: {} repos to create, {} repos to sync",
+        plans.iter().filter(|plan| plan.repo.is_none()).count(),
+        plans.iter().filter(|plan| plan.repo.is_some()).count()
+    );
+    for plan in plans {
+        println!("\n{}", plan);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::process::ExitCode;
+
+use crate::error::Error;
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_PARTIAL_FAILURE: u8 = 2;
+
+pub struct Synced {
+    /// Full name of the mirror
+    pub name: String,
+    pub commits: usize,
+    pub issues: usize,
+}
+
+pub struct Failed {
+    /// The source repo or service that failed
+    pub name: String,
+    pub error: Error,
+}
+
+/// What went well, and what didn't, during a run
+#[derive(Default)]
+pub struct Report {
+    pub synced: Vec<Synced>,
+    /// What would have been synced, on a dry run
+    pub planned: Vec<Synced>,
+    pub failed: Vec<Failed>,
+}
+
+impl Report {
+    pub fn fail(&mut self, name: impl Into<String>, error: Error) {
+        self.failed.push(Failed {
+            name: name.into(),
+            error,
+        });
+    }
+
+    pub fn extend(&mut self, other: Report) {
+        self.synced.extend(other.synced);
+        self.planned.extend(other.planned);
+        self.failed.extend(other.failed);
+    }
+
+    /// 0 when everything synced, 1 when nothing did, and 2 when only some of it did. On a dry run,
+    /// the same goes for what could be planned
+    pub fn exit_code(&self) -> ExitCode {
+        let nothing_went_well = self.synced.is_empty() && self.planned.is_empty();
+        match (nothing_went_well, self.failed.is_empty()) {
+            (_, true) => ExitCode::SUCCESS,
+            (true, false) => ExitCode::from(EXIT_FAILURE),
+            (false, false) => ExitCode::from(EXIT_PARTIAL_FAILURE),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.planned.is_empty() {
+            writeln!(
+                f,
+                "Summary: {} repos synced, {} failed",
+                self.synced.len(),
+                self.failed.len()
+            )?;
+        } else {
+            writeln!(
+                f,
+                "Summary: {} repos would be synced, {} failed",
+                self.planned.len(),
+                self.failed.len()
+            )?;
+        }
+        for synced in self.synced.iter().chain(&self.planned) {
+            if synced.commits > 0 || synced.issues > 0 {
+                writeln!(
+                    f,
+                    "  {}: {} commits, {} issues",
+                    synced.name, synced.commits, synced.issues
+                )?;
+            }
+        }
+        for failed in &self.failed {
+            writeln!(f, "  {} failed: {}", failed.name, failed.error)?;
+        }
+        Ok(())
+    }
+}
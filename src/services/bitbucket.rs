@@ -0,0 +1,349 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{parse_raw_author, Auth, ServiceClient, ServiceConfig};
+
+const BITBUCKET_API_URL: &str = "https://api.bitbucket.org/2.0/";
+
+/// A page of results. The link to the next page is left out on the last one
+#[derive(Deserialize)]
+struct Page<T> {
+    values: Vec<T>,
+    next: Option<Url>,
+}
+
+impl<T> http::LinkedPage for Page<T> {
+    type Item = T;
+
+    fn into_parts(self) -> (Vec<T>, Option<Url>) {
+        (self.values, self.next)
+    }
+}
+
+#[derive(Deserialize)]
+struct BitbucketUser {
+    uuid: String,
+}
+
+#[derive(Deserialize)]
+struct WorkspaceMembership {
+    workspace: Workspace,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    slug: String,
+}
+
+#[derive(Deserialize)]
+struct Link {
+    href: Url,
+}
+
+#[derive(Deserialize)]
+struct CloneLink {
+    name: String,
+    href: Url,
+}
+
+#[derive(Deserialize)]
+struct RepoLinks {
+    html: Link,
+    #[serde(default)]
+    clone: Vec<CloneLink>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketRepo {
+    slug: String,
+    full_name: String,
+    #[serde(default)]
+    description: String,
+    is_private: bool,
+    created_on: DateTime<FixedOffset>,
+    links: RepoLinks,
+}
+
+#[derive(Deserialize)]
+struct HtmlLinks {
+    html: Link,
+}
+
+#[derive(Deserialize)]
+struct CommitAuthor {
+    /// Like "Name <email>"
+    raw: String,
+    /// The account the author is linked to, if any
+    user: Option<BitbucketUser>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketCommit {
+    hash: String,
+    date: DateTime<FixedOffset>,
+    message: String,
+    author: CommitAuthor,
+    links: HtmlLinks,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    id: u64,
+    title: String,
+    state: String,
+    created_on: DateTime<FixedOffset>,
+    author: BitbucketUser,
+    closed_by: Option<BitbucketUser>,
+    links: HtmlLinks,
+}
+
+/// An entry of the activity of a pull request, which is either an update, an approval or a comment
+#[derive(Deserialize)]
+struct PullRequestActivity {
+    update: Option<PullRequestUpdate>,
+}
+
+#[derive(Deserialize)]
+struct PullRequestUpdate {
+    state: String,
+    date: DateTime<FixedOffset>,
+}
+
+/// Reads the repos of every workspace the user is a member of on Bitbucket Cloud
+pub struct BitbucketClient {
+    api_url: Url,
+    username: String,
+    token: String,
+    auth: Auth,
+    client: reqwest::Client,
+}
+
+impl BitbucketClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            // Bitbucket Cloud is only at bitbucket.org, with the API on its own host
+            api_url: Url::parse(BITBUCKET_API_URL)?,
+            username: config.username.clone(),
+            token: config.token.clone(),
+            auth: config.auth,
+        })
+    }
+
+    /// GET a resource with the credentials of the user
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Option<T>> {
+        let request = self
+            .auth
+            .apply(self.client.get(url), &self.username, &self.token);
+        http::get_json(request).await
+    }
+
+    /// Every value of a paged resource
+    async fn get_all<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Vec<T>> {
+        http::get_linked(url, |url| self.get::<Page<T>>(url)).await
+    }
+
+    fn to_activity_repo(&self, workspace: &str, repo: &BitbucketRepo) -> activity::Repository {
+        let clone_url = repo
+            .links
+            .clone
+            .iter()
+            .find(|link| link.name == "https")
+            .map_or_else(|| repo.links.html.href.clone(), |link| link.href.clone());
+
+        activity::Repository {
+            // Everyone has a workspace of their own, named after them
+            owned_by_you: workspace.to_lowercase() == self.username.to_lowercase(),
+            owner: workspace.to_string(),
+            name: repo.slug.clone(),
+            description: (!repo.description.is_empty()).then(|| repo.description.clone()),
+            full_name: repo.full_name.clone(),
+            html_url: repo.links.html.href.clone(),
+            clone_url,
+            private: repo.is_private,
+            created_date: repo.created_on,
+        }
+    }
+
+    fn to_commit_activity(&self, commit: BitbucketCommit) -> activity::Activity {
+        let (name, email) = parse_raw_author(&commit.author.raw);
+        activity::Activity {
+            op_type: OpType::CommitRepo,
+            date: commit.date,
+            content: ActivityContent::Commit(activity::Commit {
+                sha1: commit.hash,
+                message: commit.message.trim().to_string(),
+                author_email: email.clone(),
+                author_name: name,
+                timestamp: commit.date,
+            }),
+            source_link: commit.links.html.href,
+            username: self.username.clone(),
+            email,
+        }
+    }
+
+    /// When a pull request was merged, from the update that merged it in its activity
+    async fn merged_on(
+        &self,
+        workspace: &str,
+        repo_slug: &str,
+        pull_request: &PullRequest,
+    ) -> anyhow::Result<Option<DateTime<FixedOffset>>> {
+        let url = http::join(
+            &self.api_url,
+            &[
+                "repositories",
+                workspace,
+                repo_slug,
+                "pullrequests",
+                &pull_request.id.to_string(),
+                "activity",
+            ],
+        );
+        let activities: Vec<PullRequestActivity> = self.get_all(url).await?;
+        Ok(activities
+            .into_iter()
+            .filter_map(|activity| activity.update)
+            .find(|update| update.state == "MERGED")
+            .map(|update| update.date))
+    }
+
+    /// The pull requests created by the user, and the ones merged by them. `merged_on` is only
+    /// given for those merged by the user
+    fn to_pull_request_activities(
+        &self,
+        user: &BitbucketUser,
+        pull_request: &PullRequest,
+        merged_on: Option<DateTime<FixedOffset>>,
+    ) -> Vec<activity::Activity> {
+        let mut events = vec![];
+        if pull_request.author.uuid == user.uuid {
+            events.push((OpType::CreatePullRequest, pull_request.created_on));
+        }
+        if let Some(merged_on) = merged_on {
+            events.push((OpType::MergePullRequest, merged_on));
+        }
+
+        events
+            .into_iter()
+            .map(|(op_type, date)| activity::Activity {
+                op_type,
+                date,
+                content: ActivityContent::Issue(activity::Issue {
+                    issue_id: pull_request.id,
+                    message: pull_request.title.clone(),
+                }),
+                source_link: pull_request.links.html.href.clone(),
+                username: self.username.clone(),
+                // Bitbucket keeps the emails of the accounts private
+                email: String::new(),
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ServiceClient for BitbucketClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+
+        let user: BitbucketUser = self
+            .get(http::join(&self.api_url, &["user"]))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Bitbucket user {} not found", self.username))?;
+
+        let workspaces: Vec<WorkspaceMembership> = self
+            .get_all(http::join(
+                &self.api_url,
+                &["user", "permissions", "workspaces"],
+            ))
+            .await?;
+
+        for membership in workspaces {
+            let workspace = membership.workspace.slug;
+            let mut url = http::join(&self.api_url, &["repositories", &workspace]);
+            url.query_pairs_mut().append_pair("pagelen", "100");
+            let bitbucket_repos: Vec<BitbucketRepo> = self.get_all(url).await?;
+
+            for bitbucket_repo in bitbucket_repos {
+                let mut activities: HashSet<activity::Activity> = HashSet::new();
+
+                // Commits of every branch. Empty repos have no commits, and give a 404
+                let mut url = http::join(
+                    &self.api_url,
+                    &["repositories", &workspace, &bitbucket_repo.slug, "commits"],
+                );
+                url.query_pairs_mut().append_pair("pagelen", "100");
+                let commits: Vec<BitbucketCommit> = self.get_all(url).await?;
+                activities.extend(
+                    commits
+                        .into_iter()
+                        // Only commits with an email linked to the account can be told apart
+                        .filter(|commit| {
+                            commit
+                                .author
+                                .user
+                                .as_ref()
+                                .is_some_and(|author| author.uuid == user.uuid)
+                        })
+                        .map(|commit| self.to_commit_activity(commit)),
+                );
+
+                let mut url = http::join(
+                    &self.api_url,
+                    &[
+                        "repositories",
+                        &workspace,
+                        &bitbucket_repo.slug,
+                        "pullrequests",
+                    ],
+                );
+                url.query_pairs_mut()
+                    .append_pair("state", "OPEN")
+                    .append_pair("state", "MERGED")
+                    .append_pair("state", "DECLINED")
+                    .append_pair("state", "SUPERSEDED")
+                    .append_pair("pagelen", "50");
+                let pull_requests: Vec<PullRequest> = self.get_all(url).await?;
+                for pull_request in &pull_requests {
+                    let merged_by_user = pull_request.state == "MERGED"
+                        && pull_request
+                            .closed_by
+                            .as_ref()
+                            .is_some_and(|closed_by| closed_by.uuid == user.uuid);
+                    // The pull request itself only has the date of its last update, which
+                    // comments after the merge move on
+                    let merged_on = if merged_by_user {
+                        self.merged_on(&workspace, &bitbucket_repo.slug, pull_request)
+                            .await?
+                    } else {
+                        None
+                    };
+                    activities.extend(self.to_pull_request_activities(
+                        &user,
+                        pull_request,
+                        merged_on,
+                    ));
+                }
+
+                if !activities.is_empty() {
+                    repos.insert(
+                        self.to_activity_repo(&workspace, &bitbucket_repo),
+                        activities,
+                    );
+                }
+            }
+        }
+
+        Ok(repos)
+    }
+}
@@ -0,0 +1,411 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{Auth, ServiceClient, ServiceConfig};
+
+/// How many values are asked for in each page. The server may give less
+const PAGE_LIMIT: u32 = 100;
+
+/// A page of results. `nextPageStart` is left out on the last one
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    values: Vec<T>,
+    is_last_page: bool,
+    next_page_start: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerUser {
+    slug: String,
+    #[serde(default)]
+    email_address: String,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct Link {
+    href: Url,
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RepoLinks {
+    #[serde(rename = "self")]
+    self_: Vec<Link>,
+    #[serde(default)]
+    clone: Vec<Link>,
+}
+
+#[derive(Deserialize)]
+struct ServerRepo {
+    slug: String,
+    description: Option<String>,
+    #[serde(default)]
+    public: bool,
+    project: Project,
+    links: RepoLinks,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommitAuthor {
+    name: String,
+    #[serde(default)]
+    email_address: String,
+    /// Only there when the email belongs to an account
+    slug: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerCommit {
+    id: String,
+    message: String,
+    author: CommitAuthor,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    author_timestamp: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct PullRequestLinks {
+    #[serde(rename = "self")]
+    self_: Vec<Link>,
+}
+
+#[derive(Deserialize)]
+struct Participant {
+    user: ServerUser,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    id: u64,
+    title: String,
+    author: Participant,
+    #[serde(default)]
+    reviewers: Vec<Participant>,
+    #[serde(default)]
+    participants: Vec<Participant>,
+    links: PullRequestLinks,
+}
+
+#[derive(Deserialize)]
+struct Comment {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestActivity {
+    action: String,
+    user: ServerUser,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    created_date: DateTime<Utc>,
+    comment: Option<Comment>,
+}
+
+/// Reads the project and personal repos of a Bitbucket Server or Data Center install, which has a
+/// different API than Bitbucket Cloud
+pub struct BitbucketServerClient {
+    api_url: Url,
+    username: String,
+    token: String,
+    auth: Auth,
+    client: reqwest::Client,
+}
+
+impl BitbucketServerClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_url: http::join(&config.url, &["rest", "api", "1.0"]),
+            username: config.username.clone(),
+            token: config.token.clone(),
+            auth: config.auth,
+        })
+    }
+
+    /// API URL of something in the repo
+    fn repo_endpoint(&self, repo: &ServerRepo, segments: &[&str]) -> Url {
+        http::join(
+            &self.api_url,
+            &[
+                &["projects", &repo.project.key, "repos", &repo.slug],
+                segments,
+            ]
+            .concat(),
+        )
+    }
+
+    /// GET a resource with the credentials of the user
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Option<T>> {
+        let request = self
+            .auth
+            .apply(self.client.get(url), &self.username, &self.token);
+        http::get_json(request).await
+    }
+
+    /// Every value of a paged resource
+    async fn get_all<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Vec<T>> {
+        let mut values = vec![];
+        let mut start = 0;
+        loop {
+            let mut page_url = url.clone();
+            page_url
+                .query_pairs_mut()
+                .append_pair("start", &start.to_string())
+                .append_pair("limit", &PAGE_LIMIT.to_string());
+
+            let Some(page) = self.get::<Page<T>>(page_url).await? else {
+                break;
+            };
+            values.extend(page.values);
+            match page.next_page_start {
+                Some(next_page_start) if !page.is_last_page => start = next_page_start,
+                _ => break,
+            }
+        }
+        Ok(values)
+    }
+
+    /// The repos of all the projects the user can see, and their personal repos
+    async fn repos(&self) -> anyhow::Result<Vec<ServerRepo>> {
+        let mut repos: Vec<ServerRepo> = self
+            .get_all(http::join(
+                &self.api_url,
+                &["users", &self.username, "repos"],
+            ))
+            .await?;
+
+        let projects: Vec<Project> = self
+            .get_all(http::join(&self.api_url, &["projects"]))
+            .await?;
+        for project in projects {
+            repos.extend(
+                self.get_all::<ServerRepo>(http::join(
+                    &self.api_url,
+                    &["projects", &project.key, "repos"],
+                ))
+                .await?,
+            );
+        }
+
+        Ok(repos)
+    }
+
+    /// Link to the repo in the web interface, without the "/browse" at the end
+    fn html_url(repo: &ServerRepo) -> anyhow::Result<Url> {
+        let link = repo
+            .links
+            .self_
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No link to the repo {}", repo.slug))?;
+        let mut html_url = link.href.clone();
+        html_url
+            .path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid link to the repo {}", repo.slug))?
+            .pop_if_empty()
+            .pop();
+        Ok(html_url)
+    }
+
+    fn to_activity_repo(
+        &self,
+        repo: &ServerRepo,
+        html_url: Url,
+        activities: &HashSet<activity::Activity>,
+    ) -> activity::Repository {
+        let clone_url = repo
+            .links
+            .clone
+            .iter()
+            .find(|link| link.name == "http")
+            .map_or_else(|| html_url.clone(), |link| link.href.clone());
+        // Personal projects have the key of the user, like "~JDOE"
+        let personal = repo.project.key.starts_with('~');
+
+        activity::Repository {
+            owned_by_you: personal
+                && repo.project.key[1..].to_lowercase() == self.username.to_lowercase(),
+            owner: repo.project.key.trim_start_matches('~').to_lowercase(),
+            name: repo.slug.clone(),
+            description: repo.description.clone().filter(|d| !d.is_empty()),
+            full_name: format!("{}/{}", repo.project.key, repo.slug),
+            html_url,
+            clone_url,
+            private: !repo.public,
+            // The API doesn't tell when the repo was created, so the first activity stands in
+            created_date: activities
+                .iter()
+                .map(|activity| activity.date)
+                .min()
+                .unwrap_or_else(|| Utc::now().fixed_offset()),
+        }
+    }
+
+    fn is_you(&self, user: &ServerUser) -> bool {
+        user.slug.to_lowercase() == self.username.to_lowercase()
+    }
+
+    async fn commit_activities(
+        &self,
+        user: &ServerUser,
+        repo: &ServerRepo,
+        html_url: &Url,
+    ) -> anyhow::Result<Vec<activity::Activity>> {
+        // Empty repos have no commits, and give a 404
+        let commits: Vec<ServerCommit> =
+            self.get_all(self.repo_endpoint(repo, &["commits"])).await?;
+
+        Ok(commits
+            .into_iter()
+            .filter(|commit| match &commit.author.slug {
+                Some(slug) => slug.to_lowercase() == user.slug.to_lowercase(),
+                None => {
+                    !user.email_address.is_empty()
+                        && commit.author.email_address.to_lowercase()
+                            == user.email_address.to_lowercase()
+                }
+            })
+            .map(|commit| {
+                let date = commit.author_timestamp.fixed_offset();
+                let source_link = http::join(html_url, &["commits", &commit.id]);
+
+                activity::Activity {
+                    op_type: OpType::CommitRepo,
+                    date,
+                    content: ActivityContent::Commit(activity::Commit {
+                        sha1: commit.id,
+                        message: commit.message.trim().to_string(),
+                        author_email: commit.author.email_address.clone(),
+                        author_name: commit.author.name,
+                        timestamp: date,
+                    }),
+                    source_link,
+                    username: self.username.clone(),
+                    email: commit.author.email_address,
+                }
+            })
+            .collect())
+    }
+
+    /// What the user did in the pull requests they took part in
+    async fn pull_request_activities(
+        &self,
+        user: &ServerUser,
+        repo: &ServerRepo,
+    ) -> anyhow::Result<Vec<activity::Activity>> {
+        let mut url = self.repo_endpoint(repo, &["pull-requests"]);
+        url.query_pairs_mut().append_pair("state", "ALL");
+        let pull_requests: Vec<PullRequest> = self.get_all(url).await?;
+
+        let mut activities = vec![];
+        for pull_request in pull_requests {
+            // The rest of the pull requests can't have anything done by the user
+            let took_part = std::iter::once(&pull_request.author)
+                .chain(&pull_request.reviewers)
+                .chain(&pull_request.participants)
+                .any(|participant| self.is_you(&participant.user));
+            let Some(link) = pull_request.links.self_.first() else {
+                continue;
+            };
+            if !took_part {
+                continue;
+            }
+
+            let pr_activities: Vec<PullRequestActivity> = self
+                .get_all(self.repo_endpoint(
+                    repo,
+                    &["pull-requests", &pull_request.id.to_string(), "activities"],
+                ))
+                .await?;
+            for pr_activity in pr_activities {
+                if !self.is_you(&pr_activity.user) {
+                    continue;
+                }
+
+                let issue = ActivityContent::Issue(activity::Issue {
+                    issue_id: pull_request.id,
+                    message: pull_request.title.clone(),
+                });
+                let mut source_link = link.href.clone();
+                let (op_type, content) = match (pr_activity.action.as_str(), pr_activity.comment) {
+                    ("OPENED", _) => (OpType::CreatePullRequest, issue),
+                    ("MERGED", _) => (OpType::MergePullRequest, issue),
+                    ("DECLINED", _) => (OpType::ClosePullRequest, issue),
+                    ("APPROVED", _) => (OpType::ApprovePullRequest, issue),
+                    ("COMMENTED", Some(comment)) => {
+                        source_link
+                            .query_pairs_mut()
+                            .append_pair("commentId", &comment.id.to_string());
+                        let content = ActivityContent::Comment(activity::Comment {
+                            comment_id: comment.id,
+                            issue_id: pull_request.id,
+                            message: pull_request.title.clone(),
+                        });
+                        (OpType::CommentPull, content)
+                    }
+                    _ => continue,
+                };
+
+                activities.push(activity::Activity {
+                    op_type,
+                    date: pr_activity.created_date.fixed_offset(),
+                    content,
+                    source_link,
+                    username: self.username.clone(),
+                    email: user.email_address.clone(),
+                });
+            }
+        }
+
+        Ok(activities)
+    }
+}
+
+#[async_trait]
+impl ServiceClient for BitbucketServerClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+
+        let user: ServerUser = self
+            .get(http::join(&self.api_url, &["users", &self.username]))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Bitbucket user {} not found", self.username))?;
+
+        for server_repo in self.repos().await? {
+            let html_url = Self::html_url(&server_repo)?;
+
+            let mut activities: HashSet<activity::Activity> = HashSet::new();
+            activities.extend(
+                self.commit_activities(&user, &server_repo, &html_url)
+                    .await?,
+            );
+            activities.extend(self.pull_request_activities(&user, &server_repo).await?);
+
+            if !activities.is_empty() {
+                repos.insert(
+                    self.to_activity_repo(&server_repo, html_url, &activities),
+                    activities,
+                );
+            }
+        }
+
+        Ok(repos)
+    }
+}
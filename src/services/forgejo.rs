@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+
+use crate::activity;
+
+use super::gitea::GiteaClient;
+use super::{ServiceClient, ServiceConfig};
+
+/// Forgejo is a fork of Gitea, and the activity feeds are still the same. What differs is how the
+/// versions are told apart, which decides if the instance has the feeds at all
+pub struct ForgejoClient {
+    gitea: GiteaClient,
+}
+
+impl ForgejoClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            gitea: GiteaClient::new(config)?,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceClient for ForgejoClient {
+    async fn check_version(&self) -> anyhow::Result<()> {
+        // Forgejo has its own versioning since 7.0, while its Gitea API version, like
+        // "7.0.0+gitea-1.22.0", tells which endpoints are there. Instances without the Forgejo
+        // endpoint are Gitea, or a Forgejo from before 1.18, which are checked as Gitea
+        let forgejo_version = self
+            .gitea
+            .version(&["api", "forgejo", "v1", "version"])
+            .await?;
+        let (version, api_version) = self.gitea.api_version().await?;
+
+        match forgejo_version {
+            Some(forgejo_version) => self.gitea.use_api_version(
+                api_version,
+                &format!("Forgejo {}", forgejo_version),
+                "Forgejo",
+            ),
+            None => self
+                .gitea
+                .use_api_version(api_version, &format!("Gitea {}", version), "Gitea"),
+        }
+    }
+
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        self.gitea.get_repos().await
+    }
+
+    fn parse_webhook(
+        &self,
+        event: &str,
+        body: &[u8],
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        self.gitea.parse_webhook(event, body)
+    }
+}
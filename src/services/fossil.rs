@@ -0,0 +1,422 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::{git, http};
+
+use super::{ServiceClient, ServiceConfig};
+
+/// Fossil keeps its times as Julian days, this is the one of the Unix epoch
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+
+/// Everything read out of one repo file
+struct FossilRepo {
+    path: PathBuf,
+    project_name: Option<String>,
+    description: Option<String>,
+    /// Where the repo was cloned from, which is also where its web pages are
+    web_url: Option<Url>,
+    /// The contact info of the user, when it is an email
+    email: String,
+    checkins: Vec<CheckIn>,
+    ticket_changes: Vec<TicketChange>,
+    /// Time of the first event, standing in for the creation of the repo
+    first_event: Option<f64>,
+}
+
+struct CheckIn {
+    uuid: String,
+    mtime: f64,
+    comment: String,
+}
+
+struct TicketChange {
+    ticket_id: u64,
+    ticket_uuid: String,
+    title: String,
+    change_id: u64,
+    mtime: f64,
+    comment: String,
+    /// The first change of a ticket is the one opening it
+    is_first: bool,
+}
+
+fn julian_to_date(julian_day: f64) -> DateTime<FixedOffset> {
+    let millis = ((julian_day - UNIX_EPOCH_JULIAN_DAY) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .fixed_offset()
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> anyhow::Result<bool> {
+    Ok(conn
+        .query_row(
+            "SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2",
+            [table, column],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+fn config_value(conn: &Connection, name: &str) -> anyhow::Result<Option<String>> {
+    Ok(conn
+        .query_row("SELECT value FROM config WHERE name = ?1", [name], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?
+        .filter(|value| !value.is_empty()))
+}
+
+/// Read the check-ins and the ticket changes of the user out of a repo file. The file is opened
+/// read-only, so it can be read while a Fossil server has it open
+fn read_repo(path: &Path, username: &str) -> anyhow::Result<FossilRepo> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    // Only a remote it can be synced with has web pages to link to
+    let web_url = config_value(&conn, "last-sync-url")?
+        .and_then(|url| Url::parse(&url).ok())
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(|mut url| {
+            // Credentials can be kept in the sync URL
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url
+        });
+
+    let email = conn
+        .query_row(
+            "SELECT info FROM user WHERE login = ?1",
+            [username],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()?
+        .flatten()
+        .filter(|info| info.contains('@'))
+        .unwrap_or_default();
+
+    // The user and the comment can be edited afterwards, which is kept apart from the originals
+    let mut statement = conn.prepare(
+        "SELECT blob.uuid, event.mtime, coalesce(event.ecomment, event.comment, '')
+         FROM event JOIN blob ON blob.rid = event.objid
+         WHERE event.type = 'ci' AND coalesce(event.euser, event.user) = ?1",
+    )?;
+    let checkins = statement
+        .query_map([username], |row| {
+            Ok(CheckIn {
+                uuid: row.get(0)?,
+                mtime: row.get(1)?,
+                comment: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Tickets can have a schema of their own, they are only read when they have the default fields
+    let mut ticket_changes = vec![];
+    if has_column(&conn, "ticket", "title")?
+        && has_column(&conn, "ticketchng", "login")?
+        && has_column(&conn, "ticketchng", "icomment")?
+    {
+        let mut statement = conn.prepare(
+            "SELECT ticket.tkt_id, ticket.tkt_uuid, coalesce(ticket.title, ''),
+                ticketchng.tkt_rid, ticketchng.tkt_mtime, coalesce(ticketchng.icomment, ''),
+                ticketchng.tkt_mtime = (
+                    SELECT min(first.tkt_mtime) FROM ticketchng AS first
+                    WHERE first.tkt_id = ticket.tkt_id
+                )
+             FROM ticketchng JOIN ticket ON ticket.tkt_id = ticketchng.tkt_id
+             WHERE ticketchng.login = ?1",
+        )?;
+        ticket_changes = statement
+            .query_map([username], |row| {
+                Ok(TicketChange {
+                    ticket_id: row.get::<_, i64>(0)? as u64,
+                    ticket_uuid: row.get(1)?,
+                    title: row.get(2)?,
+                    change_id: row.get::<_, i64>(3)? as u64,
+                    mtime: row.get(4)?,
+                    comment: row.get(5)?,
+                    is_first: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    Ok(FossilRepo {
+        path: path.to_path_buf(),
+        project_name: config_value(&conn, "project-name")?,
+        description: config_value(&conn, "project-description")?,
+        web_url,
+        email,
+        checkins,
+        ticket_changes,
+        first_event: conn.query_row("SELECT min(mtime) FROM event", [], |row| row.get(0))?,
+    })
+}
+
+/// Run the fossil binary, which is only needed for the repos on a server. Nothing is read from
+/// stdin, so a server asking for a password fails rather than waiting for one
+async fn run_fossil(args: &[&str]) -> anyhow::Result<()> {
+    let output = Command::new("fossil")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("Failed to execute fossil, it has to be installed for repos on a server")?;
+
+    if !output.status.success() {
+        // The arguments are left out, as the URL can have the password in it
+        anyhow::bail!(
+            "fossil {} failed with exit code {:?}\nstderr: {}",
+            args[0],
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Where the repos are read from
+enum Source {
+    /// A repo file, or a directory of them
+    Local(PathBuf),
+    /// A repo on a Fossil server, cloned to a file in the workspace and pulled on the next runs
+    Remote { url: Url, path: PathBuf },
+}
+
+/// Reads Fossil repos straight out of their files, which are SQLite databases. The URL points to
+/// a repo file or a directory of them, or to a repo on a server, which is cloned with the fossil
+/// binary first
+pub struct FossilClient {
+    source: Source,
+    username: String,
+    /// Password of the user on the server, for the repos that aren't public
+    token: String,
+}
+
+impl FossilClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        let source = match config.url.scheme() {
+            "file" => Source::Local(
+                config
+                    .url
+                    .to_file_path()
+                    .map_err(|_| anyhow::anyhow!("Invalid Fossil repo path {}", config.url))?,
+            ),
+            "http" | "https" => {
+                let host = config.url.host_str().context("Fossil URL without a host")?;
+                let name = config
+                    .url
+                    .path_segments()
+                    .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+                    .unwrap_or(host);
+                Source::Remote {
+                    url: config.url.clone(),
+                    path: git::workspace("fossil", host).join(format!("{}.fossil", name)),
+                }
+            }
+            scheme => anyhow::bail!(
+                "Fossil repos are read from file:// or http(s):// URLs, not {}://",
+                scheme
+            ),
+        };
+
+        Ok(Self {
+            source,
+            username: config.username.clone(),
+            token: config.token.clone(),
+        })
+    }
+
+    /// Clone the repo from the server, or pull it when it was cloned on an earlier run
+    async fn update_clone(&self, url: &Url, path: &Path) -> anyhow::Result<()> {
+        let mut url = url.clone();
+        if !self.token.is_empty() {
+            let _ = url.set_username(&self.username);
+            let _ = url.set_password(Some(&self.token));
+        }
+        let path_str = path.to_str().context("Invalid workspace path")?;
+
+        if path.exists() {
+            // The URL is given every time, so a changed token is picked up
+            run_fossil(&["pull", url.as_str(), "-R", path_str, "--once"]).await
+        } else {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            run_fossil(&["clone", url.as_str(), path_str]).await
+        }
+    }
+
+    /// The repo files to read, which are the ones ending in ".fossil" for a directory
+    fn repo_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let path = match &self.source {
+            Source::Remote { path, .. } => return Ok(vec![path.clone()]),
+            Source::Local(path) => path,
+        };
+        if !path.is_dir() {
+            return Ok(vec![path.clone()]);
+        }
+
+        let mut files = vec![];
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "fossil") {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Link to a page of the repo when it has a web URL, otherwise to the file itself
+    fn link(&self, repo: &FossilRepo, segments: &[&str]) -> anyhow::Result<Url> {
+        match &repo.web_url {
+            Some(web_url) => Ok(http::join(web_url, segments)),
+            None => Url::from_file_path(&repo.path)
+                .map_err(|_| anyhow::anyhow!("Invalid path {}", repo.path.display())),
+        }
+    }
+
+    fn to_activity_repo(&self, repo: &FossilRepo) -> anyhow::Result<activity::Repository> {
+        let name = repo
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let html_url = self.link(repo, &[])?;
+
+        Ok(activity::Repository {
+            // The repos are the ones set up by the user
+            owned_by_you: true,
+            owner: self.username.clone(),
+            full_name: repo.project_name.clone().unwrap_or_else(|| name.clone()),
+            name,
+            description: repo.description.clone(),
+            clone_url: html_url.clone(),
+            html_url,
+            // There's no telling if the repo is served to anyone
+            private: true,
+            created_date: julian_to_date(repo.first_event.unwrap_or(UNIX_EPOCH_JULIAN_DAY)),
+        })
+    }
+
+    fn to_activities(&self, repo: &FossilRepo) -> anyhow::Result<HashSet<activity::Activity>> {
+        let mut activities = HashSet::new();
+
+        for checkin in &repo.checkins {
+            let date = julian_to_date(checkin.mtime);
+            activities.insert(activity::Activity {
+                op_type: OpType::CommitRepo,
+                date,
+                content: ActivityContent::Commit(activity::Commit {
+                    sha1: checkin.uuid.clone(),
+                    message: checkin.comment.trim().to_string(),
+                    author_email: repo.email.clone(),
+                    author_name: self.username.clone(),
+                    timestamp: date,
+                }),
+                source_link: self.link(repo, &["info", &checkin.uuid])?,
+                username: self.username.clone(),
+                email: repo.email.clone(),
+            });
+        }
+
+        for change in &repo.ticket_changes {
+            let (op_type, content) = if change.is_first {
+                (
+                    OpType::CreateIssue,
+                    ActivityContent::Issue(activity::Issue {
+                        issue_id: change.ticket_id,
+                        message: change.title.clone(),
+                    }),
+                )
+            } else if !change.comment.trim().is_empty() {
+                (
+                    OpType::CommentIssue,
+                    ActivityContent::Comment(activity::Comment {
+                        comment_id: change.change_id,
+                        issue_id: change.ticket_id,
+                        message: change.title.clone(),
+                    }),
+                )
+            } else {
+                continue;
+            };
+
+            activities.insert(activity::Activity {
+                op_type,
+                date: julian_to_date(change.mtime),
+                content,
+                source_link: self.link(repo, &["tktview", &change.ticket_uuid])?,
+                username: self.username.clone(),
+                email: repo.email.clone(),
+            });
+        }
+
+        Ok(activities)
+    }
+}
+
+#[async_trait]
+impl ServiceClient for FossilClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+
+        if let Source::Remote { url, path } = &self.source {
+            self.update_clone(url, path).await?;
+        }
+        for path in self.repo_files()? {
+            // SQLite is blocking, keep it off the async workers
+            let username = self.username.clone();
+            let read_path = path.clone();
+            let repo = tokio::task::spawn_blocking(move || read_repo(&read_path, &username))
+                .await?
+                .map_err(|e| e.context(format!("Unable to read {}", path.display())))?;
+
+            let activities = self.to_activities(&repo)?;
+            if !activities.is_empty() {
+                repos.insert(self.to_activity_repo(&repo)?, activities);
+            }
+        }
+
+        Ok(repos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn julian_days_are_utc_dates() {
+        assert_eq!(
+            julian_to_date(UNIX_EPOCH_JULIAN_DAY).to_rfc3339(),
+            "1970-01-01T00:00:00+00:00"
+        );
+        // Julian days start at noon
+        assert_eq!(
+            julian_to_date(2460311.0).to_rfc3339(),
+            "2024-01-01T12:00:00+00:00"
+        );
+        assert_eq!(
+            julian_to_date(2460311.25).to_rfc3339(),
+            "2024-01-01T18:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn julian_days_keep_the_milliseconds() {
+        // What Fossil stores for 2024-03-01 10:20:30.5
+        let date = julian_to_date(2460370.930908565);
+        assert_eq!(date.to_rfc3339(), "2024-03-01T10:20:30.500+00:00");
+    }
+}
@@ -0,0 +1,419 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{ServiceClient, ServiceConfig};
+
+/// Gerrit puts this in front of every JSON response, to keep it from being run as a script
+const XSSI_PREFIX: &str = ")]}'";
+
+/// How many changes are asked for at a time
+const PAGE_LIMIT: usize = 100;
+
+/// The label the reviews are voted on
+const CODE_REVIEW: &str = "Code-Review";
+
+/// The JSON of a response, without the prefix Gerrit puts in front of it
+fn strip_xssi(body: &str) -> &str {
+    body.strip_prefix(XSSI_PREFIX).unwrap_or(body)
+}
+
+/// Gerrit has its own format for timestamps, like "2013-02-01 09:59:32.126000000", always in UTC
+struct Timestamp(DateTime<FixedOffset>);
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let timestamp = String::deserialize(deserializer)?;
+        NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S%.f")
+            .map(|date| Timestamp(date.and_utc().fixed_offset()))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Deserialize)]
+struct Account {
+    _account_id: u64,
+    #[serde(default)]
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct ProjectInfo {
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CommitUser {
+    name: String,
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct CommitInfo {
+    author: CommitUser,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RevisionInfo {
+    /// The patch set number
+    _number: u64,
+    created: Timestamp,
+    uploader: Account,
+    commit: Option<CommitInfo>,
+}
+
+#[derive(Deserialize)]
+struct ApprovalInfo {
+    _account_id: u64,
+    value: Option<i32>,
+    date: Option<Timestamp>,
+}
+
+#[derive(Deserialize)]
+struct LabelInfo {
+    #[serde(default)]
+    all: Vec<ApprovalInfo>,
+}
+
+#[derive(Deserialize)]
+struct ChangeMessage {
+    author: Option<Account>,
+    date: Timestamp,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ChangeInfo {
+    project: String,
+    subject: String,
+    status: String,
+    created: Timestamp,
+    submitted: Option<Timestamp>,
+    _number: u64,
+    current_revision: Option<String>,
+    #[serde(default)]
+    revisions: HashMap<String, RevisionInfo>,
+    #[serde(default)]
+    labels: HashMap<String, LabelInfo>,
+    #[serde(default)]
+    messages: Vec<ChangeMessage>,
+    /// Only set on the last change of a page, when there are more
+    #[serde(default)]
+    _more_changes: bool,
+}
+
+/// Reads the changes the user owns and reviews on a Gerrit instance. Gerrit has no owners of
+/// projects, so every project is mirrored as someone else's
+pub struct GerritClient {
+    url: Url,
+    username: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl GerritClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: config.url.clone(),
+            username: config.username.clone(),
+            token: config.token.clone(),
+        })
+    }
+
+    /// GET a resource, where a 404 means it doesn't exist. Authenticated requests go under "/a/",
+    /// with the HTTP password of the user
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        query: &[(&str, &str)],
+    ) -> anyhow::Result<Option<T>> {
+        let mut url = http::join(&self.url, &[&["a"], segments].concat());
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        let request = self
+            .client
+            .get(url)
+            .basic_auth(&self.username, Some(&self.token));
+        let Some(response) = http::get_found(request).await? else {
+            return Ok(None);
+        };
+        let body = response.text().await?;
+        Ok(Some(serde_json::from_str(strip_xssi(&body))?))
+    }
+
+    /// All the changes matching the query
+    async fn changes(&self, query: &str, options: &[&str]) -> anyhow::Result<Vec<ChangeInfo>> {
+        let mut changes: Vec<ChangeInfo> = vec![];
+        loop {
+            let start = changes.len().to_string();
+            let limit = PAGE_LIMIT.to_string();
+            let mut params = vec![("q", query), ("n", limit.as_str()), ("S", start.as_str())];
+            params.extend(options.iter().map(|option| ("o", *option)));
+
+            // The trailing slash is part of the endpoint
+            let page: Vec<ChangeInfo> = self
+                .get(&["changes", ""], &params)
+                .await?
+                .unwrap_or_default();
+            let more = page.last().is_some_and(|change| change._more_changes);
+            changes.extend(page);
+            if !more {
+                break;
+            }
+        }
+        Ok(changes)
+    }
+
+    fn change_link(&self, change: &ChangeInfo) -> Url {
+        let number = change._number.to_string();
+        let segments: Vec<&str> = ["c"]
+            .into_iter()
+            .chain(change.project.split('/'))
+            .chain(["+", number.as_str()])
+            .collect();
+        http::join(&self.url, &segments)
+    }
+
+    fn pull_request_activity(
+        &self,
+        account: &Account,
+        change: &ChangeInfo,
+        op_type: OpType,
+        date: DateTime<FixedOffset>,
+    ) -> activity::Activity {
+        activity::Activity {
+            op_type,
+            date,
+            content: ActivityContent::Issue(activity::Issue {
+                issue_id: change._number,
+                message: change.subject.clone(),
+            }),
+            source_link: self.change_link(change),
+            username: self.username.clone(),
+            email: account.email.clone(),
+        }
+    }
+
+    /// Opening the change, the patch sets uploaded after it, and merging or abandoning it
+    fn owned_change_activities(
+        &self,
+        account: &Account,
+        change: &ChangeInfo,
+    ) -> Vec<activity::Activity> {
+        let mut activities = vec![self.pull_request_activity(
+            account,
+            change,
+            OpType::CreatePullRequest,
+            change.created.0,
+        )];
+
+        // The first patch set is the one the change was opened with. Others may upload patch
+        // sets to the change too, those are theirs
+        activities.extend(
+            change
+                .revisions
+                .values()
+                .filter(|revision| {
+                    revision._number > 1 && revision.uploader._account_id == account._account_id
+                })
+                .map(|revision| activity::Activity {
+                    op_type: OpType::CommitRepo,
+                    date: revision.created.0,
+                    content: ActivityContent::PatchSet(activity::PatchSet {
+                        patch_set: revision._number,
+                        issue_id: change._number,
+                        message: change.subject.clone(),
+                    }),
+                    source_link: http::join(
+                        &self.change_link(change),
+                        &[&revision._number.to_string()],
+                    ),
+                    username: self.username.clone(),
+                    email: account.email.clone(),
+                }),
+        );
+
+        match change.status.as_str() {
+            // What got merged is the commit of the last patch set
+            "MERGED" => {
+                let commit = change.current_revision.as_ref().and_then(|sha| {
+                    let commit = change.revisions.get(sha)?.commit.as_ref()?;
+                    Some((sha, commit))
+                });
+                if let (Some((sha, commit)), Some(submitted)) = (commit, &change.submitted) {
+                    activities.push(activity::Activity {
+                        op_type: OpType::CommitRepo,
+                        date: submitted.0,
+                        content: ActivityContent::Commit(activity::Commit {
+                            sha1: sha.clone(),
+                            message: commit.message.trim().to_string(),
+                            author_email: commit.author.email.clone(),
+                            author_name: commit.author.name.clone(),
+                            timestamp: submitted.0,
+                        }),
+                        source_link: self.change_link(change),
+                        username: self.username.clone(),
+                        email: commit.author.email.clone(),
+                    });
+                }
+            }
+            "ABANDONED" => {
+                // Anyone with the rights can abandon the change, only count it when it was you
+                let abandoned = change.messages.iter().rev().find(|message| {
+                    message.message.starts_with("Abandoned")
+                        && message
+                            .author
+                            .as_ref()
+                            .is_some_and(|author| author._account_id == account._account_id)
+                });
+                if let Some(abandoned) = abandoned {
+                    activities.push(self.pull_request_activity(
+                        account,
+                        change,
+                        OpType::ClosePullRequest,
+                        abandoned.date.0,
+                    ));
+                }
+            }
+            _ => {}
+        }
+
+        activities
+    }
+
+    /// The latest vote of the user on the code review of the change
+    fn review_activity(
+        &self,
+        account: &Account,
+        change: &ChangeInfo,
+    ) -> Option<activity::Activity> {
+        let vote = change
+            .labels
+            .get(CODE_REVIEW)?
+            .all
+            .iter()
+            .find(|approval| approval._account_id == account._account_id)?;
+        let op_type = match vote.value? {
+            value if value > 0 => OpType::ApprovePullRequest,
+            value if value < 0 => OpType::RejectPullRequest,
+            _ => return None,
+        };
+        Some(self.pull_request_activity(account, change, op_type, vote.date.as_ref()?.0))
+    }
+
+    async fn to_activity_repo(
+        &self,
+        project: &str,
+        activities: &HashSet<activity::Activity>,
+    ) -> anyhow::Result<activity::Repository> {
+        let info: Option<ProjectInfo> = self.get(&["projects", project], &[]).await?;
+        let segments: Vec<&str> = project.split('/').collect();
+        // Projects can be nested, like "platform/build". Top level ones are kept apart from
+        // the ones of other instances by the host
+        let (owner, name) = match project.rsplit_once('/') {
+            Some((parent, name)) => (parent.replace('/', "-"), name),
+            None => (self.url.host_str().unwrap_or_default().to_string(), project),
+        };
+
+        Ok(activity::Repository {
+            owned_by_you: false,
+            owner,
+            name: name.to_string(),
+            description: info
+                .and_then(|info| info.description)
+                .filter(|d| !d.is_empty()),
+            full_name: project.to_string(),
+            html_url: http::join(
+                &self.url,
+                &[&["admin", "repos"], segments.as_slice()].concat(),
+            ),
+            clone_url: http::join(&self.url, &segments),
+            // There's no telling who can read the project, so it is kept private
+            private: true,
+            // The API doesn't tell when the project was created, so the first activity stands in
+            created_date: activities
+                .iter()
+                .map(|activity| activity.date)
+                .min()
+                .unwrap_or_else(|| chrono::Utc::now().fixed_offset()),
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceClient for GerritClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let account: Account = self
+            .get(&["accounts", "self"], &[])
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Gerrit account {} not found", self.username))?;
+
+        let mut projects: HashMap<String, HashSet<activity::Activity>> = HashMap::new();
+        for change in self
+            .changes(
+                "owner:self",
+                &["ALL_REVISIONS", "CURRENT_COMMIT", "MESSAGES"],
+            )
+            .await?
+        {
+            projects
+                .entry(change.project.clone())
+                .or_default()
+                .extend(self.owned_change_activities(&account, &change));
+        }
+        for change in self.changes("reviewer:self", &["DETAILED_LABELS"]).await? {
+            if let Some(activity) = self.review_activity(&account, &change) {
+                projects
+                    .entry(change.project.clone())
+                    .or_default()
+                    .insert(activity);
+            }
+        }
+
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+        for (project, activities) in projects {
+            repos.insert(
+                self.to_activity_repo(&project, &activities).await?,
+                activities,
+            );
+        }
+
+        Ok(repos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_the_xssi_prefix() {
+        assert_eq!(strip_xssi(")]}'\n{\"a\": 1}"), "\n{\"a\": 1}");
+        assert_eq!(strip_xssi("[]"), "[]");
+
+        let changes: Vec<u64> = serde_json::from_str(strip_xssi(")]}'\n[1, 2]")).unwrap();
+        assert_eq!(changes, vec![1, 2]);
+    }
+
+    #[test]
+    fn parses_timestamps_as_utc() {
+        let Timestamp(date) = serde_json::from_str("\"2013-02-01 09:59:32.126000000\"").unwrap();
+        assert_eq!(date.to_rfc3339(), "2013-02-01T09:59:32.126+00:00");
+
+        let Timestamp(date) = serde_json::from_str("\"2024-02-01 12:00:00\"").unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-02-01T12:00:00+00:00");
+
+        assert!(serde_json::from_str::<Timestamp>("\"2024-02-01T12:00:00Z\"").is_err());
+    }
+}
@@ -4,11 +4,13 @@ use reqwest;
 use serde;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::io::{self, Write};
+use std::sync::OnceLock;
 use url::Url;
 
 use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
 
 use super::{ServiceClient, ServiceConfig};
 
@@ -53,7 +55,8 @@ pub struct GiteaRepo {
     pub mirror: bool,
     pub size: u64,
     pub language: String,
-    pub languages_url: Url,
+    // Only sent since Gitea 1.21
+    pub languages_url: Option<Url>,
     pub html_url: Url,
     pub url: Url,
     pub ssh_url: String,
@@ -69,7 +72,8 @@ pub struct GiteaRepo {
     pub open_issues_count: u64,
     pub open_pr_counter: u64,
     pub release_counter: u64,
-    pub archived_at: DateTime<FixedOffset>,
+    // Only sent since Gitea 1.21
+    pub archived_at: Option<DateTime<FixedOffset>>,
     pub has_issues: bool,
     pub internal_tracker: serde_json::Value,
     pub has_wiki: bool,
@@ -84,6 +88,8 @@ pub struct GiteaRepo {
     pub allow_rebase: bool,
     pub allow_rebase_explicit: bool,
     pub allow_squash_merge: bool,
+    // Only sent since Gitea 1.22
+    #[serde(default)]
     pub allow_fast_forward_only_merge: bool,
     pub allow_rebase_update: bool,
     pub default_delete_branch_after_merge: bool,
@@ -91,19 +97,11 @@ pub struct GiteaRepo {
     pub default_allow_maintainer_edit: bool,
     pub internal: bool,
     // pub mirror_interval: "",
-    pub object_format_name: String,
+    // Only sent since Gitea 1.22, before which everything was SHA-1
+    pub object_format_name: Option<String>,
     pub mirror_updated: DateTime<FixedOffset>,
 }
 
-#[derive(Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-#[serde(untagged)]
-enum GiteaContent {
-    Commit(CommitContent),
-    String(String),
-    None,
-}
-
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct CommitContent {
@@ -180,12 +178,59 @@ struct GiteaActivity {
     created: DateTime<FixedOffset>,
 }
 
+// https://docs.gitea.com/usage/webhooks
+#[derive(Deserialize)]
+struct PushPayload {
+    commits: Vec<PayloadCommit>,
+    repository: GiteaRepo,
+    pusher: GiteaUser,
+}
+
+#[derive(Deserialize)]
+struct PayloadCommit {
+    id: String,
+    message: String,
+    url: Url,
+    author: PayloadUser,
+    timestamp: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct PayloadUser {
+    name: String,
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct IssuePayload {
+    action: String,
+    issue: PayloadIssue,
+    repository: GiteaRepo,
+    sender: GiteaUser,
+}
+
+#[derive(Deserialize)]
+struct PullRequestPayload {
+    action: String,
+    pull_request: PayloadIssue,
+    repository: GiteaRepo,
+    sender: GiteaUser,
+}
+
+#[derive(Deserialize)]
+struct PayloadIssue {
+    number: u64,
+    title: String,
+    html_url: Url,
+    created_at: DateTime<FixedOffset>,
+}
+
 impl From<CommitInfo> for activity::Activity {
     fn from(commit: CommitInfo) -> activity::Activity {
         let (author_email, author_name) = commit
             .author
             .as_ref()
-            .and_then(|a| Some((a.email.clone(), a.username.clone())))
+            .map(|a| (a.email.clone(), a.username.clone()))
             .unwrap_or((
                 commit.commit.author.email.clone(),
                 commit.commit.author.name.clone(),
@@ -239,20 +284,58 @@ fn commit_info_short_to_activity(
     }
 }
 
+/// Oldest Gitea with the activity feeds API. Forgejo had the same version numbers up to 7
+const FEEDS_VERSION: Version = Version(1, 20, 0);
+
+/// Gitea that can list commits without their signatures and changed files
+const LEAN_COMMITS_VERSION: Version = Version(1, 21, 0);
+
+#[derive(Deserialize)]
+struct ServerVersion {
+    version: String,
+}
+
+/// Version of the Gitea API of an instance. Forgejo reports the Gitea version it is compatible with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(u64, u64, u64);
+
+impl Version {
+    /// Understands Gitea versions like "1.21.4" or "1.22.0+dev-12-g0a1b2c3", the Forgejo versions
+    /// before 7 like "1.21.11-1", and the later ones like "7.0.0+gitea-1.22.0"
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = match version.split_once("+gitea-") {
+            Some((_, gitea)) => gitea,
+            None => version,
+        };
+        let version = version.split(['-', '+']).next()?;
+
+        let mut numbers = version.split('.').map(|n| n.parse::<u64>());
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next().unwrap_or(Ok(0)).ok()?;
+        let patch = numbers.next().unwrap_or(Ok(0)).ok()?;
+        Some(Self(major, minor, patch))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
 pub struct GiteaClient {
+    url: Url,
     api_url: Url,
     username: String,
     _token: Option<String>,
     client: reqwest::Client,
+    /// Gitea API version of the instance, once it has been checked
+    api_version: OnceLock<Version>,
 }
 
 impl GiteaClient {
     pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
-        let mut api_url = config.url.clone();
-        api_url
-            .path_segments_mut()
-            .map_err(|_| anyhow::anyhow!("Invalid base URL"))?
-            .extend("/api/v1".split('/'));
+        let api_url = http::join(&config.url, &["api", "v1"]);
 
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
@@ -267,11 +350,57 @@ impl GiteaClient {
         Ok(Self {
             client,
             username: config.username.clone(),
+            url: config.url.clone(),
             api_url,
             _token: Some(config.token.clone()),
+            api_version: OnceLock::new(),
         })
     }
 
+    /// The version at an endpoint under the base URL, or None if there is no such endpoint
+    pub async fn version(&self, segments: &[&str]) -> anyhow::Result<Option<String>> {
+        let url = http::join(&self.url, segments);
+        let version: Option<ServerVersion> = http::get_json(self.client.get(url)).await?;
+        Ok(version.map(|version| version.version))
+    }
+
+    /// Version of the Gitea API, as given and as parsed
+    pub async fn api_version(&self) -> anyhow::Result<(String, Version)> {
+        let version = self
+            .version(&["api", "v1", "version"])
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No Gitea API found at {}", self.url))?;
+        let api_version = Version::parse(&version)
+            .ok_or_else(|| anyhow::anyhow!("Unknown version \"{}\" of {}", version, self.url))?;
+        Ok((version, api_version))
+    }
+
+    /// Fail if the instance is too old to have the activity feeds, otherwise keep its API version
+    /// for the endpoints that differ between versions. `running` is what the instance runs, like
+    /// "Forgejo 1.19.3-0", and `product` what the minimum version is told in
+    pub fn use_api_version(
+        &self,
+        api_version: Version,
+        running: &str,
+        product: &str,
+    ) -> anyhow::Result<()> {
+        if api_version < FEEDS_VERSION {
+            anyhow::bail!(
+                "{} runs {}, which is too old for the activity feeds. {} {} or newer is needed",
+                self.url,
+                running,
+                product,
+                FEEDS_VERSION
+            );
+        }
+        let _ = self.api_version.set(api_version);
+        Ok(())
+    }
+
+    fn is_you(&self, user: &GiteaUser) -> bool {
+        user.username.to_lowercase() == self.username.to_lowercase()
+    }
+
     fn to_activity_repo(&self, gitea_repo: &GiteaRepo) -> activity::Repository {
         activity::Repository {
             owned_by_you: self.username.to_lowercase() == gitea_repo.owner.username.to_lowercase(),
@@ -290,6 +419,11 @@ impl GiteaClient {
 
 #[async_trait]
 impl ServiceClient for GiteaClient {
+    async fn check_version(&self) -> anyhow::Result<()> {
+        let (version, api_version) = self.api_version().await?;
+        self.use_api_version(api_version, &format!("Gitea {}", version), "Gitea")
+    }
+
     async fn get_repos(
         &self,
     ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
@@ -302,10 +436,7 @@ impl ServiceClient for GiteaClient {
                 self.api_url, self.username, page, limit
             );
 
-            let result: Vec<GiteaActivity> = self
-                .client
-                .get(&url)
-                .send()
+            let result: Vec<GiteaActivity> = http::send(self.client.get(&url))
                 .await?
                 .error_for_status()?
                 .json()
@@ -318,7 +449,7 @@ impl ServiceClient for GiteaClient {
 
             for activity in result {
                 let repo = self.to_activity_repo(&activity.repo);
-                let activities = repos.entry(repo).or_insert_with(HashSet::new);
+                let activities = repos.entry(repo).or_default();
 
                 match activity.op_type {
                     OpType::CommitRepo => {
@@ -332,7 +463,7 @@ impl ServiceClient for GiteaClient {
 
                         let mut count: i64 = (c.len as i64) - (c.commits.len() as i64);
 
-                        let last_sha1 = c.commits.last().and_then(|lc| Some(lc.sha1.clone()));
+                        let last_sha1 = c.commits.last().map(|lc| lc.sha1.clone());
                         activities.extend(
                             c.commits
                                 .into_iter()
@@ -346,19 +477,25 @@ impl ServiceClient for GiteaClient {
                             let limit2 = count * 2;
 
                             'scroller: loop {
-                                let url = format!(
-                                    "{}/repos/{}/commits?sha={}&page={}&limit={}",
+                                let mut url = format!(
+                                    "{}/repos/{}/commits?sha={}&page={}&limit={}&stat=false",
                                     self.api_url,
                                     activity.repo.full_name,
                                     last_sha1.clone().unwrap(),
                                     page2,
                                     limit2
                                 );
+                                // Leaving these out makes the listing a lot faster, on the
+                                // versions that can
+                                if self
+                                    .api_version
+                                    .get()
+                                    .is_some_and(|version| *version >= LEAN_COMMITS_VERSION)
+                                {
+                                    url.push_str("&verification=false&files=false");
+                                }
 
-                                let result: Vec<CommitInfo> = self
-                                    .client
-                                    .get(&url)
-                                    .send()
+                                let result: Vec<CommitInfo> = http::send(self.client.get(&url))
                                     .await?
                                     .error_for_status()?
                                     .json()
@@ -367,15 +504,10 @@ impl ServiceClient for GiteaClient {
                                 // Gitea API will return an empty array if the limit + page goes beyond the activity
                                 if result.is_empty() {
                                     // Should technically never land here
-                                    print!("!");
-                                    io::stdout().flush().ok().expect("Could not flush stdout");
                                     break 'scroller;
                                 }
 
                                 for commit in result {
-                                    print!(".");
-                                    io::stdout().flush().ok().expect("Could not flush stdout");
-
                                     if 0 >= count {
                                         break 'scroller;
                                     }
@@ -458,8 +590,91 @@ impl ServiceClient for GiteaClient {
             }
 
             page += 1;
-            print!(".");
-            io::stdout().flush().ok().expect("Could not flush stdout");
+        }
+
+        Ok(repos)
+    }
+
+    fn parse_webhook(
+        &self,
+        event: &str,
+        body: &[u8],
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+
+        match event {
+            "push" => {
+                let payload: PushPayload = serde_json::from_slice(body)?;
+                if !self.is_you(&payload.pusher) {
+                    return Ok(repos);
+                }
+
+                let activities = repos
+                    .entry(self.to_activity_repo(&payload.repository))
+                    .or_default();
+                for commit in payload.commits {
+                    activities.insert(activity::Activity {
+                        op_type: OpType::CommitRepo,
+                        date: commit.timestamp,
+                        content: ActivityContent::Commit(activity::Commit {
+                            sha1: commit.id,
+                            message: commit.message.trim().to_string(),
+                            author_email: commit.author.email.clone(),
+                            author_name: commit.author.name.clone(),
+                            timestamp: commit.timestamp,
+                        }),
+                        source_link: commit.url,
+                        username: payload.pusher.username.clone(),
+                        email: commit.author.email,
+                    });
+                }
+            }
+            "issues" => {
+                let payload: IssuePayload = serde_json::from_slice(body)?;
+                // Only newly opened issues show up in the activity feed as well
+                if payload.action != "opened" || !self.is_you(&payload.sender) {
+                    return Ok(repos);
+                }
+
+                repos
+                    .entry(self.to_activity_repo(&payload.repository))
+                    .or_default()
+                    .insert(activity::Activity {
+                        op_type: OpType::CreateIssue,
+                        date: payload.issue.created_at,
+                        content: ActivityContent::Issue(activity::Issue {
+                            issue_id: payload.issue.number,
+                            message: payload.issue.title,
+                        }),
+                        source_link: payload.issue.html_url,
+                        username: payload.sender.username,
+                        email: payload.sender.email,
+                    });
+            }
+            "pull_request" => {
+                let payload: PullRequestPayload = serde_json::from_slice(body)?;
+                if payload.action != "opened" || !self.is_you(&payload.sender) {
+                    return Ok(repos);
+                }
+
+                // Pull requests and issues share the same numbering, so they can be mirrored
+                // the same way
+                repos
+                    .entry(self.to_activity_repo(&payload.repository))
+                    .or_default()
+                    .insert(activity::Activity {
+                        op_type: OpType::CreatePullRequest,
+                        date: payload.pull_request.created_at,
+                        content: ActivityContent::Issue(activity::Issue {
+                            issue_id: payload.pull_request.number,
+                            message: payload.pull_request.title,
+                        }),
+                        source_link: payload.pull_request.html_url,
+                        username: payload.sender.username,
+                        email: payload.sender.email,
+                    });
+            }
+            _ => {} // The rest are not supported yet
         }
 
         Ok(repos)
@@ -481,6 +696,13 @@ impl Hash for activity::Activity {
                 i.issue_id.hash(state);
                 i.message.hash(state);
             }
+            ActivityContent::Comment(c) => {
+                c.comment_id.hash(state);
+            }
+            ActivityContent::PatchSet(p) => {
+                p.issue_id.hash(state);
+                p.patch_set.hash(state);
+            }
         };
     }
 }
@@ -499,7 +721,54 @@ impl PartialEq for activity::Activity {
             (ActivityContent::Issue(i1), ActivityContent::Issue(i2)) => {
                 i1.message == i2.message && i1.issue_id == i2.issue_id
             }
+            (ActivityContent::Comment(c1), ActivityContent::Comment(c2)) => {
+                c1.comment_id == c2.comment_id
+            }
+            (ActivityContent::PatchSet(p1), ActivityContent::PatchSet(p2)) => {
+                p1.issue_id == p2.issue_id && p1.patch_set == p2.patch_set
+            }
             _ => false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gitea_versions_are_parsed() {
+        assert_eq!(Version::parse("1.21.0"), Some(Version(1, 21, 0)));
+        assert_eq!(
+            Version::parse("1.22.0+dev-12-g0a1b2c3"),
+            Some(Version(1, 22, 0))
+        );
+        assert_eq!(Version::parse("1.21"), Some(Version(1, 21, 0)));
+        assert_eq!(Version::parse("development"), None);
+    }
+
+    #[test]
+    fn pre_releases_count_as_their_release() {
+        assert_eq!(Version::parse("1.22.0-rc1"), Some(Version(1, 22, 0)));
+        assert_eq!(
+            Version::parse("1.21.0-rc0+dev-5-gabcdef0"),
+            Some(Version(1, 21, 0))
+        );
+        assert!(Version::parse("1.21.0-rc1").unwrap() >= LEAN_COMMITS_VERSION);
+    }
+
+    #[test]
+    fn forgejo_versions_map_to_gitea() {
+        // Before 7, Forgejo had the numbers of the Gitea it was made from
+        assert_eq!(Version::parse("1.21.11-1"), Some(Version(1, 21, 11)));
+        assert_eq!(
+            Version::parse("7.0.0+gitea-1.22.0"),
+            Some(Version(1, 22, 0))
+        );
+        assert_eq!(
+            Version::parse("9.0.0-dev-123-abcdef+gitea-1.22.0"),
+            Some(Version(1, 22, 0))
+        );
+        assert!(Version::parse("1.19.3-0").unwrap() < FEEDS_VERSION);
+    }
+}
@@ -0,0 +1,363 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{ServiceClient, ServiceConfig};
+
+#[derive(Deserialize)]
+struct GitlabUser {
+    id: u64,
+    public_email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitlabEvent {
+    project_id: Option<u64>,
+    action_name: String,
+    target_iid: Option<u64>,
+    target_type: Option<String>,
+    target_title: Option<String>,
+    created_at: DateTime<FixedOffset>,
+    push_data: Option<PushData>,
+    note: Option<GitlabNote>,
+}
+
+#[derive(Deserialize)]
+struct GitlabNote {
+    id: u64,
+    noteable_type: String,
+    noteable_iid: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PushData {
+    commit_count: u64,
+    action: String,
+    ref_type: String,
+    commit_from: Option<String>,
+    commit_to: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitlabNamespace {
+    kind: String,
+    path: String,
+    full_path: String,
+}
+
+#[derive(Deserialize)]
+struct GitlabProject {
+    path: String,
+    path_with_namespace: String,
+    description: Option<String>,
+    web_url: Url,
+    http_url_to_repo: Url,
+    visibility: String,
+    created_at: DateTime<FixedOffset>,
+    namespace: GitlabNamespace,
+}
+
+#[derive(Deserialize)]
+struct GitlabCommit {
+    id: String,
+    message: String,
+    author_name: String,
+    author_email: String,
+    committed_date: DateTime<FixedOffset>,
+    web_url: Url,
+}
+
+/// A project the events happened in, along with the activities found so far
+struct Project {
+    gitlab_project: GitlabProject,
+    repo: activity::Repository,
+    activities: HashSet<activity::Activity>,
+    /// Commits can show up in more than one push, like when a branch is pushed again
+    seen_commits: HashSet<String>,
+}
+
+pub struct GitlabClient {
+    api_url: Url,
+    username: String,
+    client: reqwest::Client,
+}
+
+impl GitlabClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        let api_url = http::join(&config.url, &["api", "v4"]);
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "PRIVATE-TOKEN",
+            reqwest::header::HeaderValue::from_str(&config.token)?,
+        );
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            username: config.username.clone(),
+            api_url,
+        })
+    }
+
+    async fn user(&self) -> anyhow::Result<GitlabUser> {
+        let mut url = http::join(&self.api_url, &["users"]);
+        url.query_pairs_mut()
+            .append_pair("username", &self.username);
+        let users: Vec<GitlabUser> = http::get_json(self.client.get(url))
+            .await?
+            .unwrap_or_default();
+        users
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("GitLab user {} not found", self.username))
+    }
+
+    fn to_activity_repo(&self, project: &GitlabProject) -> activity::Repository {
+        activity::Repository {
+            owned_by_you: project.namespace.kind == "user"
+                && project.namespace.path.to_lowercase() == self.username.to_lowercase(),
+            // Groups can be nested, and the owner ends up in the name of the mirror
+            owner: project.namespace.full_path.replace('/', "-"),
+            name: project.path.clone(),
+            description: project.description.clone().filter(|d| !d.is_empty()),
+            full_name: project.path_with_namespace.clone(),
+            html_url: project.web_url.clone(),
+            clone_url: project.http_url_to_repo.clone(),
+            private: project.visibility != "public",
+            created_date: project.created_at,
+        }
+    }
+
+    /// The commits of a push, newest first, walking back from the head of the push
+    async fn push_commits(
+        &self,
+        project_id: u64,
+        push_data: &PushData,
+    ) -> anyhow::Result<Vec<GitlabCommit>> {
+        let Some(commit_to) = &push_data.commit_to else {
+            return Ok(vec![]);
+        };
+
+        let mut commits = vec![];
+        let per_page = push_data.commit_count.min(100);
+        let mut page = 1;
+        'scroller: loop {
+            let mut url = http::join(
+                &self.api_url,
+                &["projects", &project_id.to_string(), "repository", "commits"],
+            );
+            url.query_pairs_mut()
+                .append_pair("ref_name", commit_to)
+                .append_pair("per_page", &per_page.to_string())
+                .append_pair("page", &page.to_string());
+
+            let result: Vec<GitlabCommit> = http::get_json(self.client.get(url))
+                .await?
+                .unwrap_or_default();
+            if result.is_empty() {
+                break;
+            }
+
+            for commit in result {
+                if commits.len() as u64 >= push_data.commit_count
+                    || push_data.commit_from.as_ref() == Some(&commit.id)
+                {
+                    break 'scroller;
+                }
+                commits.push(commit);
+            }
+
+            page += 1;
+        }
+
+        Ok(commits)
+    }
+
+    /// Turn merge request, issue and comment events into an activity
+    fn to_activity(
+        &self,
+        user: &GitlabUser,
+        project: &Project,
+        event: &GitlabEvent,
+    ) -> Option<activity::Activity> {
+        let title = event.target_title.clone().unwrap_or_default();
+        let (op_type, content, path) = match (
+            event.target_type.as_deref()?,
+            event.action_name.as_str(),
+            &event.note,
+        ) {
+            ("Note" | "DiffNote" | "DiscussionNote", "commented on", Some(note)) => {
+                let (op_type, path) = match note.noteable_type.as_str() {
+                    "MergeRequest" => (OpType::CommentPull, "merge_requests"),
+                    "Issue" => (OpType::CommentIssue, "issues"),
+                    // Comments on commits and snippets aren't tied to anything that is mirrored
+                    _ => return None,
+                };
+                let issue_id = note.noteable_iid?;
+                let content = ActivityContent::Comment(activity::Comment {
+                    comment_id: note.id,
+                    issue_id,
+                    message: title,
+                });
+                let path = format!("{}/{}#note_{}", path, issue_id, note.id);
+                (op_type, content, path)
+            }
+            (target_type @ ("MergeRequest" | "Issue"), action, _) => {
+                let op_type = match (target_type, action) {
+                    ("MergeRequest", "opened") => OpType::CreatePullRequest,
+                    // Merging shows up as "accepted" in the events
+                    ("MergeRequest", "accepted" | "merged") => OpType::MergePullRequest,
+                    ("MergeRequest", "closed") => OpType::ClosePullRequest,
+                    ("MergeRequest", "approved") => OpType::ApprovePullRequest,
+                    ("Issue", "opened") => OpType::CreateIssue,
+                    ("Issue", "closed") => OpType::CloseIssue,
+                    _ => return None,
+                };
+                let issue_id = event.target_iid?;
+                let path = match target_type {
+                    "MergeRequest" => format!("merge_requests/{}", issue_id),
+                    _ => format!("issues/{}", issue_id),
+                };
+                let content = ActivityContent::Issue(activity::Issue {
+                    issue_id,
+                    message: title,
+                });
+                (op_type, content, path)
+            }
+            _ => return None,
+        };
+
+        let source_link = Url::parse(&format!(
+            "{}/-/{}",
+            project
+                .gitlab_project
+                .web_url
+                .as_str()
+                .trim_end_matches('/'),
+            path
+        ))
+        .ok()?;
+
+        Some(activity::Activity {
+            op_type,
+            date: event.created_at,
+            content,
+            source_link,
+            username: self.username.clone(),
+            email: user.public_email.clone().unwrap_or_default(),
+        })
+    }
+
+    async fn add_push(
+        &self,
+        project_id: u64,
+        project: &mut Project,
+        push_data: &PushData,
+    ) -> anyhow::Result<()> {
+        // Creating a branch from an existing commit, or a force push to an older one, pushes no
+        // new commits, while the event still points to the commit the branch is at
+        if push_data.ref_type != "branch"
+            || push_data.action == "removed"
+            || push_data.commit_count == 0
+        {
+            return Ok(());
+        }
+
+        // The event only has the title of the last commit, and the date of the push, so even a
+        // single commit is looked up for its whole message and author
+        for commit in self.push_commits(project_id, push_data).await? {
+            if !project.seen_commits.insert(commit.id.clone()) {
+                continue;
+            }
+            project.activities.insert(activity::Activity {
+                op_type: OpType::CommitRepo,
+                date: commit.committed_date,
+                content: ActivityContent::Commit(activity::Commit {
+                    sha1: commit.id,
+                    message: commit.message.trim().to_string(),
+                    author_email: commit.author_email.clone(),
+                    author_name: commit.author_name,
+                    timestamp: commit.committed_date,
+                }),
+                source_link: commit.web_url,
+                username: self.username.clone(),
+                email: commit.author_email,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ServiceClient for GitlabClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let user = self.user().await?;
+
+        // Projects that can't be seen anymore are None, so they are only looked up once
+        let mut projects: HashMap<u64, Option<Project>> = HashMap::new();
+        let mut page = 1;
+        let per_page = 100;
+        loop {
+            let mut url = http::join(&self.api_url, &["users", &user.id.to_string(), "events"]);
+            url.query_pairs_mut()
+                .append_pair("per_page", &per_page.to_string())
+                .append_pair("page", &page.to_string());
+
+            let result: Vec<GitlabEvent> = http::get_json(self.client.get(url))
+                .await?
+                .unwrap_or_default();
+
+            // GitLab API will return an empty array if the page goes beyond the events
+            if result.is_empty() {
+                break;
+            }
+
+            for event in result {
+                let Some(project_id) = event.project_id else {
+                    continue;
+                };
+                if let Entry::Vacant(entry) = projects.entry(project_id) {
+                    let url = http::join(&self.api_url, &["projects", &project_id.to_string()]);
+                    let project = http::get_json::<GitlabProject>(self.client.get(url)).await?;
+                    entry.insert(project.map(|gitlab_project| Project {
+                        repo: self.to_activity_repo(&gitlab_project),
+                        gitlab_project,
+                        activities: HashSet::new(),
+                        seen_commits: HashSet::new(),
+                    }));
+                }
+                let Some(Some(project)) = projects.get_mut(&project_id) else {
+                    continue;
+                };
+
+                if let ("pushed to" | "pushed new", Some(push_data)) =
+                    (event.action_name.as_str(), &event.push_data)
+                {
+                    self.add_push(project_id, project, push_data).await?;
+                } else if let Some(activity) = self.to_activity(&user, project, &event) {
+                    project.activities.insert(activity);
+                }
+            }
+
+            page += 1;
+        }
+
+        Ok(projects
+            .into_values()
+            .flatten()
+            .filter(|project| !project.activities.is_empty())
+            .map(|project| (project.repo, project.activities))
+            .collect())
+    }
+}
@@ -0,0 +1,199 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{ServiceClient, ServiceConfig};
+
+/// How many of the latest commits on the default branch are looked at in each repo. Gogs can't
+/// page through the commits, so they come in one go
+const COMMIT_LIMIT: u32 = 1000;
+
+#[derive(Deserialize)]
+struct GogsUser {
+    username: String,
+    #[serde(default)]
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct GogsRepo {
+    owner: GogsUser,
+    name: String,
+    full_name: String,
+    #[serde(default)]
+    description: String,
+    private: bool,
+    #[serde(default)]
+    empty: bool,
+    #[serde(default)]
+    mirror: bool,
+    html_url: Url,
+    clone_url: Url,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct GogsCommitUser {
+    name: String,
+    email: String,
+    date: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct GogsCommitDetails {
+    author: GogsCommitUser,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct GogsCommit {
+    sha: String,
+    commit: GogsCommitDetails,
+    /// The account the author email belongs to, if any
+    author: Option<GogsUser>,
+}
+
+/// Gogs has the same kind of API as Gitea, but without the activity feeds. The activities are found
+/// by going through the commits of every repo the user has access to instead
+pub struct GogsClient {
+    api_url: Url,
+    username: String,
+    client: reqwest::Client,
+}
+
+impl GogsClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        // Gogs serves the API under the same path as Gitea
+        let api_url = http::join(&config.url, &["api", "v1"]);
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            reqwest::header::HeaderValue::from_str(&format!("token {}", config.token))?,
+        );
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            username: config.username.clone(),
+            api_url,
+        })
+    }
+
+    /// The latest commits on the default branch, newest first
+    async fn commits(&self, repo: &GogsRepo) -> anyhow::Result<Vec<GogsCommit>> {
+        let mut url = http::join(
+            &self.api_url,
+            &["repos", &repo.owner.username, &repo.name, "commits"],
+        );
+        url.query_pairs_mut()
+            .append_pair("pageSize", &COMMIT_LIMIT.to_string());
+
+        // Listing the commits was added in Gogs 0.12
+        http::get_json(self.client.get(url)).await?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unable to list the commits of {}, Gogs 0.12 or newer is needed",
+                repo.full_name
+            )
+        })
+    }
+
+    /// If the commit was made by the user, either through the account the author email belongs
+    /// to, or by the email itself
+    fn is_yours(&self, user: &GogsUser, commit: &GogsCommit) -> bool {
+        match &commit.author {
+            Some(author) => author.username.to_lowercase() == self.username.to_lowercase(),
+            None => {
+                !user.email.is_empty()
+                    && commit.commit.author.email.to_lowercase() == user.email.to_lowercase()
+            }
+        }
+    }
+
+    fn to_activity_repo(&self, repo: &GogsRepo) -> activity::Repository {
+        activity::Repository {
+            owned_by_you: repo.owner.username.to_lowercase() == self.username.to_lowercase(),
+            owner: repo.owner.username.clone(),
+            name: repo.name.clone(),
+            full_name: repo.full_name.clone(),
+            html_url: repo.html_url.clone(),
+            clone_url: repo.clone_url.clone(),
+            private: repo.private,
+            description: (!repo.description.is_empty()).then(|| repo.description.clone()),
+            created_date: repo.created_at,
+        }
+    }
+
+    fn to_activity(&self, repo: &GogsRepo, commit: GogsCommit) -> activity::Activity {
+        let source_link = http::join(&repo.html_url, &["commit", &commit.sha]);
+
+        let author = commit.commit.author;
+        activity::Activity {
+            op_type: OpType::CommitRepo,
+            date: author.date,
+            content: ActivityContent::Commit(activity::Commit {
+                sha1: commit.sha,
+                message: commit.commit.message.trim().to_string(),
+                author_email: author.email.clone(),
+                author_name: author.name,
+                timestamp: author.date,
+            }),
+            source_link,
+            username: self.username.clone(),
+            email: author.email,
+        }
+    }
+}
+
+#[async_trait]
+impl ServiceClient for GogsClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+
+        let user: GogsUser = http::get_json(
+            self.client
+                .get(http::join(&self.api_url, &["users", &self.username])),
+        )
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Gogs user {} not found", self.username))?;
+
+        // Every repo the token has access to, including the ones of organizations and the ones
+        // the user collaborates on. Gogs doesn't page these
+        let gogs_repos: Vec<GogsRepo> = http::get_json(
+            self.client
+                .get(http::join(&self.api_url, &["user", "repos"])),
+        )
+        .await?
+        .unwrap_or_default();
+
+        for gogs_repo in gogs_repos {
+            // Empty repos have no commits to list, and mirrors are someone else's activity
+            if gogs_repo.empty || gogs_repo.mirror {
+                continue;
+            }
+
+            let activities: HashSet<activity::Activity> = self
+                .commits(&gogs_repo)
+                .await?
+                .into_iter()
+                .filter(|commit| self.is_yours(&user, commit))
+                .map(|commit| self.to_activity(&gogs_repo, commit))
+                .collect();
+            if !activities.is_empty() {
+                repos.insert(self.to_activity_repo(&gogs_repo), activities);
+            }
+        }
+
+        Ok(repos)
+    }
+}
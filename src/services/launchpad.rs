@@ -0,0 +1,619 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use rand::Rng;
+use serde::Deserialize;
+use sha1_smol::Sha1;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::{git, http};
+
+use super::{ServiceClient, ServiceConfig};
+
+/// Consumer key the OAuth tokens have to be made for
+const CONSUMER_KEY: &str = "github-activity-mirror";
+
+/// Every status a bug can have, as searches only give the open ones otherwise
+const BUG_STATUSES: [&str; 11] = [
+    "New",
+    "Incomplete",
+    "Opinion",
+    "Invalid",
+    "Won't Fix",
+    "Expired",
+    "Confirmed",
+    "Triaged",
+    "In Progress",
+    "Fix Committed",
+    "Fix Released",
+];
+
+/// Every status a merge proposal can have
+const MERGE_PROPOSAL_STATUSES: [&str; 8] = [
+    "Work in progress",
+    "Needs review",
+    "Approved",
+    "Rejected",
+    "Merged",
+    "Code failed to merge",
+    "Queued",
+    "Superseded",
+];
+
+/// A page of a collection, with the link to the next one
+#[derive(Deserialize)]
+struct Collection<T> {
+    entries: Vec<T>,
+    next_collection_link: Option<Url>,
+}
+
+impl<T> http::LinkedPage for Collection<T> {
+    type Item = T;
+
+    fn into_parts(self) -> (Vec<T>, Option<Url>) {
+        (self.entries, self.next_collection_link)
+    }
+}
+
+#[derive(Deserialize)]
+struct Person {
+    self_link: Url,
+    display_name: String,
+    preferred_email_address_link: Option<Url>,
+}
+
+#[derive(Deserialize)]
+struct EmailAddress {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct GitRepository {
+    self_link: Url,
+    /// Like "~someone/project/+git/repo", or "~someone/+git/repo" for personal ones
+    unique_name: String,
+    name: String,
+    description: Option<String>,
+    git_https_url: Url,
+    web_link: Url,
+    #[serde(default)]
+    private: bool,
+    date_created: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct BugTask {
+    bug_link: Url,
+    target_link: Url,
+    date_created: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct Bug {
+    id: u64,
+    title: String,
+    web_link: Url,
+}
+
+/// A project, or a package of a distribution, that bugs are filed against
+#[derive(Deserialize)]
+struct BugTarget {
+    display_name: String,
+    summary: Option<String>,
+    web_link: Url,
+    date_created: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Deserialize)]
+struct MergeProposal {
+    self_link: Url,
+    web_link: Url,
+    registrant_link: Url,
+    target_git_repository_link: Option<Url>,
+    source_git_path: Option<String>,
+    target_git_path: Option<String>,
+    commit_message: Option<String>,
+    description: Option<String>,
+    date_created: DateTime<FixedOffset>,
+}
+
+/// Where activities are put, by the API link of a git repo, or of the project or package a bug
+/// is filed against
+#[derive(PartialEq, Eq, Hash)]
+enum Place {
+    GitRepository(Url),
+    BugTarget(Url),
+}
+
+/// OAuth credentials, signed with PLAINTEXT as Launchpad wants
+struct OAuthToken {
+    token: String,
+    secret: String,
+}
+
+impl OAuthToken {
+    fn header(&self) -> String {
+        let nonce: u64 = rand::rng().random();
+        format!(
+            "OAuth realm=\"https://api.launchpad.net/\", oauth_consumer_key=\"{}\", \
+             oauth_token=\"{}\", oauth_signature_method=\"PLAINTEXT\", \
+             oauth_signature=\"&{}\", oauth_timestamp=\"{}\", oauth_nonce=\"{}\", \
+             oauth_version=\"1.0\"",
+            CONSUMER_KEY,
+            self.token,
+            self.secret,
+            chrono::Utc::now().timestamp(),
+            nonce
+        )
+    }
+}
+
+/// Reads the git repos of the user on Launchpad, along with the bugs they filed and the merge
+/// proposals they made. The API has no way to list commits, so the repos are cloned with git to
+/// read their logs
+pub struct LaunchpadClient {
+    api_url: Url,
+    username: String,
+    /// Without it, only the public data is read
+    oauth: Option<OAuthToken>,
+    workspace: PathBuf,
+    client: reqwest::Client,
+}
+
+impl LaunchpadClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        // The API lives on its own subdomain, like api.launchpad.net for launchpad.net
+        let host = config
+            .url
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid base URL"))?;
+        let mut api_url = config.url.clone();
+        api_url.set_host(Some(&format!("api.{}", host)))?;
+        api_url.set_path("devel/");
+
+        let oauth = match config.token.split_once(':') {
+            Some((token, secret)) => Some(OAuthToken {
+                token: token.to_string(),
+                secret: secret.to_string(),
+            }),
+            None if config.token.is_empty() => None,
+            None => anyhow::bail!("The Launchpad token must be like \"<token>:<secret>\""),
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_url,
+            username: config.username.trim_start_matches('~').to_string(),
+            oauth,
+            // Keep the clones of different instances apart, and away from the targets
+            workspace: git::workspace("launchpad", host),
+        })
+    }
+
+    /// GET a resource, signed with OAuth when there is a token
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Option<T>> {
+        let mut request = self.client.get(url);
+        if let Some(oauth) = &self.oauth {
+            request = request.header(reqwest::header::AUTHORIZATION, oauth.header());
+        }
+        http::get_json(request).await
+    }
+
+    /// Every entry of a collection
+    async fn get_all<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Vec<T>> {
+        http::get_linked(url, |url| self.get::<Collection<T>>(url)).await
+    }
+
+    /// Call a named operation on a resource, with its parameters
+    fn operation(&self, mut url: Url, name: &str, params: &[(&str, &str)]) -> Url {
+        url.query_pairs_mut()
+            .append_pair("ws.op", name)
+            .append_pair("ws.size", "300")
+            .extend_pairs(params);
+        url
+    }
+
+    /// The emails the commits of the user are made with. They are only given to the user
+    /// themselves, so without OAuth, the commits are found by the name of the user instead
+    async fn emails(&self, person: &Person) -> anyhow::Result<HashSet<String>> {
+        let mut emails = HashSet::new();
+        let Some(link) = &person.preferred_email_address_link else {
+            return Ok(emails);
+        };
+        if self.oauth.is_none() {
+            return Ok(emails);
+        }
+
+        if let Some(email) = self.get::<EmailAddress>(link.clone()).await? {
+            emails.insert(email.email.to_lowercase());
+        }
+        let url = http::join(&person.self_link, &["confirmed_email_addresses"]);
+        for email in self.get_all::<EmailAddress>(url).await? {
+            emails.insert(email.email.to_lowercase());
+        }
+        Ok(emails)
+    }
+
+    /// An access token to clone a private repo with, as git doesn't take the OAuth token. There
+    /// is no scope for reading only, so the token could push too, which is why it expires soon
+    async fn access_token(&self, repo: &GitRepository) -> anyhow::Result<String> {
+        let oauth = self
+            .oauth
+            .as_ref()
+            .context("Private repos can only be read with an OAuth token")?;
+        let expires = (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339();
+        let request = self
+            .client
+            .post(repo.self_link.clone())
+            .header(reqwest::header::AUTHORIZATION, oauth.header())
+            .form(&[
+                ("ws.op", "issueAccessToken"),
+                ("description", CONSUMER_KEY),
+                ("scopes", "[\"repository:push\"]"),
+                ("date_expires", &expires),
+            ]);
+        Ok(http::send(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Clone the repo, or fetch it when it was cloned on an earlier run. Only the commits are
+    /// needed, so the files are left out. Private repos are read with an access token of theirs
+    async fn update_clone(
+        &self,
+        repo: &GitRepository,
+        access_token: Option<&str>,
+    ) -> anyhow::Result<PathBuf> {
+        let path = self
+            .workspace
+            .join(&Sha1::from(repo.unique_name.as_bytes()).digest().to_string()[..8]);
+
+        let args: Vec<&str> = if path.exists() {
+            vec![
+                "fetch",
+                "--quiet",
+                "--prune",
+                "--force",
+                "origin",
+                "refs/heads/*:refs/heads/*",
+            ]
+        } else {
+            tokio::fs::create_dir_all(&self.workspace).await?;
+            vec![
+                "clone",
+                "--quiet",
+                "--bare",
+                "--filter=blob:none",
+                repo.git_https_url.as_str(),
+                path.to_str().context("Invalid workspace path")?,
+            ]
+        };
+        let cwd = if path.exists() {
+            &path
+        } else {
+            &self.workspace
+        };
+        match access_token {
+            Some(token) => {
+                git::run_git_command_with_credentials(cwd, &args, &self.username, token).await?
+            }
+            None => git::run_git_command(cwd, &args).await?,
+        };
+        Ok(path)
+    }
+
+    /// The commits on every branch of the repo made by the user
+    async fn commit_activities(
+        &self,
+        person: &Person,
+        emails: &HashSet<String>,
+        repo: &GitRepository,
+        access_token: Option<&str>,
+    ) -> anyhow::Result<Vec<activity::Activity>> {
+        let path = self.update_clone(repo, access_token).await?;
+        // Fields are split by the unit separator, and commits by the record separator, as the
+        // messages can have any other character
+        let log = git::run_git_command(
+            &path,
+            &["log", "--all", "--format=%H%x1f%ae%x1f%an%x1f%aI%x1f%B%x1e"],
+        )
+        .await?;
+
+        let mut activities = vec![];
+        for record in log.split('\x1e') {
+            let mut fields = record.trim_start_matches('\n').splitn(5, '\x1f');
+            let (Some(sha1), Some(email), Some(name), Some(date), Some(message)) = (
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+            ) else {
+                continue;
+            };
+
+            let is_yours = if emails.is_empty() {
+                name == person.display_name
+            } else {
+                emails.contains(&email.to_lowercase())
+            };
+            if !is_yours {
+                continue;
+            }
+
+            let date = DateTime::parse_from_rfc3339(date)?;
+            let mut source_link = http::join(&repo.git_https_url, &["commit", ""]);
+            source_link.query_pairs_mut().append_pair("id", sha1);
+
+            activities.push(activity::Activity {
+                op_type: OpType::CommitRepo,
+                date,
+                content: ActivityContent::Commit(activity::Commit {
+                    sha1: sha1.to_string(),
+                    message: message.trim().to_string(),
+                    author_email: email.to_string(),
+                    author_name: name.to_string(),
+                    timestamp: date,
+                }),
+                source_link,
+                username: self.username.clone(),
+                email: email.to_string(),
+            });
+        }
+
+        Ok(activities)
+    }
+
+    /// Same as `commit_activities`, for a private repo
+    async fn private_commit_activities(
+        &self,
+        person: &Person,
+        emails: &HashSet<String>,
+        repo: &GitRepository,
+    ) -> anyhow::Result<Vec<activity::Activity>> {
+        let token = self.access_token(repo).await?;
+        self.commit_activities(person, emails, repo, Some(&token))
+            .await
+    }
+
+    fn to_activity_repo(&self, repo: &GitRepository) -> activity::Repository {
+        // "~someone/project/+git/repo" is owned by "someone", and named "project-repo", or only
+        // "project" when the repo is named after it
+        let mut parts = repo.unique_name.split('/');
+        let owner = parts.next().unwrap_or_default().trim_start_matches('~');
+        let name = match parts.next() {
+            Some(project) if project != "+git" && project != repo.name => {
+                format!("{}-{}", project, repo.name)
+            }
+            _ => repo.name.clone(),
+        };
+
+        activity::Repository {
+            owned_by_you: owner == self.username,
+            owner: owner.to_string(),
+            name,
+            description: repo.description.clone().filter(|d| !d.is_empty()),
+            full_name: repo.unique_name.clone(),
+            html_url: repo.web_link.clone(),
+            clone_url: repo.git_https_url.clone(),
+            private: repo.private,
+            created_date: repo.date_created,
+        }
+    }
+
+    /// The project or package bugs are filed against, like "launchpad.net/project" or
+    /// "ubuntu/+source/package", which is owned by the distribution
+    fn to_target_repo(
+        &self,
+        target: &BugTarget,
+        activities: &HashSet<activity::Activity>,
+    ) -> activity::Repository {
+        let segments: Vec<&str> = target
+            .web_link
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty() && !segment.starts_with('+'))
+            .collect();
+        let (owner, name) = match segments.as_slice() {
+            [.., owner, name] => (owner.to_string(), name.to_string()),
+            [name] => (
+                target.web_link.host_str().unwrap_or_default().to_string(),
+                name.to_string(),
+            ),
+            [] => (
+                target.web_link.host_str().unwrap_or_default().to_string(),
+                target.display_name.clone(),
+            ),
+        };
+
+        activity::Repository {
+            owned_by_you: false,
+            owner,
+            name,
+            description: target.summary.clone().filter(|d| !d.is_empty()),
+            full_name: target.display_name.clone(),
+            html_url: target.web_link.clone(),
+            clone_url: target.web_link.clone(),
+            private: false,
+            // Packages don't tell when they were made, so the first activity stands in
+            created_date: target.date_created.unwrap_or_else(|| {
+                activities
+                    .iter()
+                    .map(|activity| activity.date)
+                    .min()
+                    .unwrap_or_else(|| chrono::Utc::now().fixed_offset())
+            }),
+        }
+    }
+
+    fn to_merge_proposal_activity(&self, proposal: &MergeProposal) -> Option<activity::Activity> {
+        // Merge proposals have no number of their own, other than the last part of their link
+        let id = proposal
+            .self_link
+            .path_segments()?
+            .next_back()?
+            .parse()
+            .ok()?;
+        let short_path = |path: &Option<String>| {
+            path.as_deref()
+                .unwrap_or_default()
+                .trim_start_matches("refs/heads/")
+                .to_string()
+        };
+        let title = [&proposal.commit_message, &proposal.description]
+            .into_iter()
+            .flatten()
+            .filter_map(|text| text.lines().find(|line| !line.trim().is_empty()))
+            .map(|line| line.trim().to_string())
+            .next()
+            .unwrap_or_else(|| {
+                format!(
+                    "Merge {} into {}",
+                    short_path(&proposal.source_git_path),
+                    short_path(&proposal.target_git_path)
+                )
+            });
+
+        Some(activity::Activity {
+            op_type: OpType::CreatePullRequest,
+            date: proposal.date_created,
+            content: ActivityContent::Issue(activity::Issue {
+                issue_id: id,
+                message: title,
+            }),
+            source_link: proposal.web_link.clone(),
+            username: self.username.clone(),
+            // Launchpad keeps the emails of the accounts private
+            email: String::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceClient for LaunchpadClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let person: Person = self
+            .get(http::join(&self.api_url, &[&format!("~{}", self.username)]))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Launchpad user {} not found", self.username))?;
+        let emails = self.emails(&person).await?;
+
+        let mut activities: HashMap<Place, HashSet<activity::Activity>> = HashMap::new();
+
+        let git_repos: HashMap<Url, GitRepository> = self
+            .get_all::<GitRepository>(self.operation(
+                http::join(&self.api_url, &["+git"]),
+                "getRepositories",
+                &[("target", person.self_link.as_str())],
+            ))
+            .await?
+            .into_iter()
+            .map(|repo| (repo.self_link.clone(), repo))
+            .collect();
+        for repo in git_repos.values() {
+            // Private repos are left out when they can't be read, rather than failing the others
+            let commits = if repo.private {
+                match self.private_commit_activities(&person, &emails, repo).await {
+                    Ok(commits) => commits,
+                    Err(e) => {
+                        eprintln!(
+                            "WARNING: Leaving out the commits of the private repo {}: {:#}",
+                            repo.unique_name, e
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                self.commit_activities(&person, &emails, repo, None).await?
+            };
+            if !commits.is_empty() {
+                activities
+                    .entry(Place::GitRepository(repo.self_link.clone()))
+                    .or_default()
+                    .extend(commits);
+            }
+        }
+
+        // A bug has a task for every project it affects, it is put with the first one
+        let mut statuses: Vec<(&str, &str)> = BUG_STATUSES.iter().map(|s| ("status", *s)).collect();
+        statuses.push(("bug_reporter", person.self_link.as_str()));
+        let tasks: Vec<BugTask> = self
+            .get_all(self.operation(person.self_link.clone(), "searchTasks", &statuses))
+            .await?;
+        let mut seen_bugs = HashSet::new();
+        for task in tasks {
+            if !seen_bugs.insert(task.bug_link.clone()) {
+                continue;
+            }
+            let Some(bug) = self.get::<Bug>(task.bug_link).await? else {
+                continue;
+            };
+            activities
+                .entry(Place::BugTarget(task.target_link))
+                .or_default()
+                .insert(activity::Activity {
+                    op_type: OpType::CreateIssue,
+                    date: task.date_created,
+                    content: ActivityContent::Issue(activity::Issue {
+                        issue_id: bug.id,
+                        message: bug.title,
+                    }),
+                    source_link: bug.web_link,
+                    username: self.username.clone(),
+                    email: String::new(),
+                });
+        }
+
+        let statuses: Vec<(&str, &str)> = MERGE_PROPOSAL_STATUSES
+            .iter()
+            .map(|s| ("status", *s))
+            .collect();
+        let proposals: Vec<MergeProposal> = self
+            .get_all(self.operation(person.self_link.clone(), "getMergeProposals", &statuses))
+            .await?;
+        for proposal in proposals {
+            // Bazaar branches are left out, only git repos are mirrored
+            let Some(target) = proposal.target_git_repository_link.clone() else {
+                continue;
+            };
+            if proposal.registrant_link != person.self_link {
+                continue;
+            }
+            if let Some(activity) = self.to_merge_proposal_activity(&proposal) {
+                activities
+                    .entry(Place::GitRepository(target))
+                    .or_default()
+                    .insert(activity);
+            }
+        }
+
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+        for (place, activities) in activities {
+            let repo = match place {
+                Place::GitRepository(link) => match git_repos.get(&link) {
+                    Some(repo) => self.to_activity_repo(repo),
+                    // The target of a merge proposal can be a repo of someone else
+                    None => match self.get::<GitRepository>(link).await? {
+                        Some(repo) => self.to_activity_repo(&repo),
+                        None => continue,
+                    },
+                },
+                Place::BugTarget(link) => match self.get::<BugTarget>(link).await? {
+                    Some(target) => self.to_target_repo(&target, &activities),
+                    None => continue,
+                },
+            };
+            repos.entry(repo).or_default().extend(activities);
+        }
+
+        Ok(repos)
+    }
+}
@@ -1,13 +1,39 @@
 use std::collections::{HashMap, HashSet};
 
 use async_trait::async_trait;
+use bitbucket::BitbucketClient;
+use bitbucket_server::BitbucketServerClient;
+use forgejo::ForgejoClient;
+use fossil::FossilClient;
+use gerrit::GerritClient;
 use gitea::GiteaClient;
+use gitlab::GitlabClient;
+use gogs::GogsClient;
+use launchpad::LaunchpadClient;
+use onedev::OnedevClient;
+use pagure::PagureClient;
+use phorge::PhorgeClient;
+use redmine::{RedmineClient, RedmineConfig};
 use serde::{Deserialize, Serialize};
+use sourcehut::SourcehutClient;
 use url::Url;
 
 use crate::activity::{Activity, Repository};
 
+pub mod bitbucket;
+pub mod bitbucket_server;
+pub mod forgejo;
+pub mod fossil;
+pub mod gerrit;
 pub mod gitea;
+pub mod gitlab;
+pub mod gogs;
+pub mod launchpad;
+pub mod onedev;
+pub mod pagure;
+pub mod phorge;
+pub mod redmine;
+pub mod sourcehut;
 
 // Taken from here https://github.com/awesome-selfhosted/awesome-selfhosted?tab=readme-ov-file#software-development---project-management
 // For now there is only support for Gitea, but would be desirable to support all these (and more!!)
@@ -15,6 +41,8 @@ pub mod gitea;
 #[serde(rename_all = "snake_case")]
 pub enum ServiceType {
     Bitbucket,
+    /// Bitbucket Server and Data Center, installed on-premises
+    BitbucketServer,
     CGit,
     Codebase,
     Codeberg,
@@ -60,8 +88,23 @@ pub enum ServiceType {
 impl ServiceType {
     pub fn create_client(&self, config: &ServiceConfig) -> anyhow::Result<Box<dyn ServiceClient>> {
         match self {
+            ServiceType::Bitbucket => Ok(Box::new(BitbucketClient::new(config)?)),
+            ServiceType::BitbucketServer => Ok(Box::new(BitbucketServerClient::new(config)?)),
+            ServiceType::Fossil => Ok(Box::new(FossilClient::new(config)?)),
+            ServiceType::Gerrit => Ok(Box::new(GerritClient::new(config)?)),
             ServiceType::Gitea => Ok(Box::new(GiteaClient::new(config)?)),
-            ServiceType::Codeberg => Ok(Box::new(GiteaClient::new(config)?)), // Pretty much identical API to Gitea
+            // Codeberg runs Forgejo
+            ServiceType::Forgejo | ServiceType::Codeberg => {
+                Ok(Box::new(ForgejoClient::new(config)?))
+            }
+            ServiceType::Gitlab => Ok(Box::new(GitlabClient::new(config)?)),
+            ServiceType::Gogs => Ok(Box::new(GogsClient::new(config)?)),
+            ServiceType::Launchpad => Ok(Box::new(LaunchpadClient::new(config)?)),
+            ServiceType::OneDev => Ok(Box::new(OnedevClient::new(config)?)),
+            ServiceType::Pagure => Ok(Box::new(PagureClient::new(config)?)),
+            ServiceType::Phorge => Ok(Box::new(PhorgeClient::new(config)?)),
+            ServiceType::Redmine => Ok(Box::new(RedmineClient::new(config)?)),
+            ServiceType::Sourcehut => Ok(Box::new(SourcehutClient::new(config)?)),
             // ... other service implementations
             _ => anyhow::bail!("Service not yet implemented: {:?}", self),
         }
@@ -74,6 +117,15 @@ pub struct ServiceConfig {
     pub username: String,
     pub url: Url,
     pub token: String,
+    /// How the token is sent, for the services that take more than one kind
+    #[serde(default)]
+    pub auth: Auth,
+    /// Secret used to sign the webhooks sent from this service
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    /// Settings only Redmine takes, under `[services.redmine]`
+    #[serde(default)]
+    pub redmine: Option<RedmineConfig>,
     #[serde(skip)] // This field won't be loaded from config
     pub client: Option<Box<dyn ServiceClient + Send + Sync>>,
 }
@@ -84,7 +136,87 @@ impl ServiceConfig {
     }
 }
 
+/// How a service is authenticated with
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    /// An access token, sent as a bearer token
+    #[default]
+    Token,
+    /// The username and the token as a password, like the app passwords of Bitbucket
+    Basic,
+}
+impl Auth {
+    pub fn apply(
+        &self,
+        request: reqwest::RequestBuilder,
+        username: &str,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        match self {
+            Auth::Token => request.bearer_auth(token),
+            Auth::Basic => request.basic_auth(username, Some(token)),
+        }
+    }
+}
+
+/// Split a git author like "Name <email>" into the name and the email
+pub fn parse_raw_author(raw: &str) -> (String, String) {
+    match raw.rsplit_once('<') {
+        Some((name, email)) => (
+            name.trim().to_string(),
+            email.trim_end_matches('>').trim().to_string(),
+        ),
+        None => (raw.trim().to_string(), String::new()),
+    }
+}
+
 #[async_trait]
 pub trait ServiceClient: Send + Sync {
+    /// Make sure the instance has the API the client needs. Called once at startup
+    async fn check_version(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     async fn get_repos(&self) -> anyhow::Result<HashMap<Repository, HashSet<Activity>>>;
+
+    /// Turn a webhook payload into activities. The signature has already been verified
+    fn parse_webhook(
+        &self,
+        _event: &str,
+        _body: &[u8],
+    ) -> anyhow::Result<HashMap<Repository, HashSet<Activity>>> {
+        anyhow::bail!("Webhooks are not supported by this service")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_raw_authors() {
+        assert_eq!(
+            parse_raw_author("Jane Doe <jane@example.com>"),
+            ("Jane Doe".to_string(), "jane@example.com".to_string())
+        );
+        // Names can have brackets of their own, the email is the last part
+        assert_eq!(
+            parse_raw_author("Jane <Doe> <jane@example.com>"),
+            ("Jane <Doe>".to_string(), "jane@example.com".to_string())
+        );
+        assert_eq!(
+            parse_raw_author("<jane@example.com>"),
+            (String::new(), "jane@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_raw_authors_without_an_email() {
+        assert_eq!(
+            parse_raw_author(" Jane Doe "),
+            ("Jane Doe".to_string(), String::new())
+        );
+        assert_eq!(parse_raw_author(""), (String::new(), String::new()));
+    }
 }
@@ -0,0 +1,304 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{Auth, ServiceClient, ServiceConfig};
+
+/// The most OneDev gives in one page
+const PAGE_SIZE: usize = 100;
+
+/// How many of the latest commits of the user are looked at in each project
+const COMMIT_LIMIT: u32 = 1000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnedevUser {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnedevProject {
+    id: u64,
+    parent_id: Option<u64>,
+    name: String,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnedevPerson {
+    name: String,
+    email_address: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    when: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnedevCommit {
+    author: OnedevPerson,
+    subject: String,
+    body: Option<String>,
+}
+
+/// An issue or a pull request, which have the same fields
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Submission {
+    number: u64,
+    title: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    submit_date: DateTime<Utc>,
+    /// Issues belong to a project, pull requests to the project they are merged into
+    #[serde(alias = "targetProjectId")]
+    project_id: u64,
+}
+
+/// Reads the projects on a OneDev instance, with the commits, issues and pull requests of the user
+pub struct OnedevClient {
+    url: Url,
+    api_url: Url,
+    username: String,
+    token: String,
+    auth: Auth,
+    client: reqwest::Client,
+}
+
+impl OnedevClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: config.url.clone(),
+            api_url: http::join(&config.url, &["~api"]),
+            username: config.username.clone(),
+            token: config.token.clone(),
+            auth: config.auth,
+        })
+    }
+
+    /// Link to something on the website, where projects are found by their path
+    fn link(&self, segments: &[&str]) -> Url {
+        let segments: Vec<&str> = segments.iter().flat_map(|s| s.split('/')).collect();
+        http::join(&self.url, &segments)
+    }
+
+    /// GET a resource with the credentials of the user
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Option<T>> {
+        let request = self
+            .auth
+            .apply(self.client.get(url), &self.username, &self.token);
+        http::get_json(request).await
+    }
+
+    /// Every item of a listing, a page at a time
+    async fn get_all<T: serde::de::DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        query: &str,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut items = vec![];
+        loop {
+            let mut url = http::join(&self.api_url, segments);
+            url.query_pairs_mut()
+                .append_pair("query", query)
+                .append_pair("offset", &items.len().to_string())
+                .append_pair("count", &PAGE_SIZE.to_string());
+
+            let page: Vec<T> = self.get(url).await?.unwrap_or_default();
+            let last_page = page.len() < PAGE_SIZE;
+            items.extend(page);
+            if last_page {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// The commits authored by the user in the project, newest first
+    async fn commit_activities(
+        &self,
+        project: u64,
+        path: &str,
+    ) -> anyhow::Result<Vec<activity::Activity>> {
+        let project = project.to_string();
+        let mut url = http::join(&self.api_url, &["repositories", &project, "commits"]);
+        url.query_pairs_mut()
+            .append_pair("query", "authored-by-me")
+            .append_pair("count", &COMMIT_LIMIT.to_string());
+        // Projects without code management have no repository
+        let hashes: Vec<String> = self.get(url).await?.unwrap_or_default();
+
+        let mut activities = vec![];
+        for hash in hashes {
+            let Some(commit) = self
+                .get::<OnedevCommit>(http::join(
+                    &self.api_url,
+                    &["repositories", &project, "commits", &hash],
+                ))
+                .await?
+            else {
+                continue;
+            };
+
+            let date = commit.author.when.fixed_offset();
+            let message = match commit.body.filter(|body| !body.trim().is_empty()) {
+                Some(body) => format!("{}\n\n{}", commit.subject, body.trim()),
+                None => commit.subject,
+            };
+            activities.push(activity::Activity {
+                op_type: OpType::CommitRepo,
+                date,
+                content: ActivityContent::Commit(activity::Commit {
+                    sha1: hash.clone(),
+                    message,
+                    author_email: commit.author.email_address.clone(),
+                    author_name: commit.author.name,
+                    timestamp: date,
+                }),
+                source_link: self.link(&[path, "~commits", &hash]),
+                username: self.username.clone(),
+                email: commit.author.email_address,
+            });
+        }
+
+        Ok(activities)
+    }
+
+    fn to_submission_activity(
+        &self,
+        op_type: OpType,
+        path: &str,
+        kind: &str,
+        submission: &Submission,
+    ) -> activity::Activity {
+        activity::Activity {
+            op_type,
+            date: submission.submit_date.fixed_offset(),
+            content: ActivityContent::Issue(activity::Issue {
+                issue_id: submission.number,
+                message: submission.title.clone(),
+            }),
+            source_link: self.link(&[path, kind, &submission.number.to_string()]),
+            username: self.username.clone(),
+            // The emails aren't part of the activities
+            email: String::new(),
+        }
+    }
+
+    fn to_activity_repo(
+        &self,
+        project: &OnedevProject,
+        path: &str,
+        activities: &HashSet<activity::Activity>,
+    ) -> activity::Repository {
+        // Projects can be nested, like "tools/build". Top level ones are kept apart from the ones
+        // of other instances by the host
+        let owner = match path.rsplit_once('/') {
+            Some((parent, _)) => parent.replace('/', "-"),
+            None => self.url.host_str().unwrap_or_default().to_string(),
+        };
+
+        activity::Repository {
+            // Projects belong to the instance, not to a user
+            owned_by_you: false,
+            owner,
+            name: project.name.clone(),
+            description: project.description.clone().filter(|d| !d.is_empty()),
+            full_name: path.to_string(),
+            html_url: self.link(&[path]),
+            clone_url: self.link(&[path]),
+            // There's no telling who can read the project, so it is kept private
+            private: true,
+            // The API doesn't tell when the project was created, so the first activity stands in
+            created_date: activities
+                .iter()
+                .map(|activity| activity.date)
+                .min()
+                .unwrap_or_else(|| Utc::now().fixed_offset()),
+        }
+    }
+}
+
+/// Path of the project, like "tools/build", made out of the names of its parents
+fn project_path(projects: &HashMap<u64, OnedevProject>, project: &OnedevProject) -> String {
+    let mut names = vec![project.name.as_str()];
+    let mut parent_id = project.parent_id;
+    while let Some(parent) = parent_id.and_then(|id| projects.get(&id)) {
+        names.push(&parent.name);
+        parent_id = parent.parent_id;
+    }
+    names.reverse();
+    names.join("/")
+}
+
+#[async_trait]
+impl ServiceClient for OnedevClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        // The queries below go by "me", which is whoever the token belongs to
+        let me: OnedevUser = self
+            .get(http::join(&self.api_url, &["users", "me"]))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("OneDev user {} not found", self.username))?;
+        if me.name != self.username {
+            anyhow::bail!(
+                "The OneDev token belongs to {}, not to {}",
+                me.name,
+                self.username
+            );
+        }
+
+        let projects: HashMap<u64, OnedevProject> = self
+            .get_all::<OnedevProject>(&["projects"], "")
+            .await?
+            .into_iter()
+            .map(|project| (project.id, project))
+            .collect();
+        let paths: HashMap<u64, String> = projects
+            .values()
+            .map(|project| (project.id, project_path(&projects, project)))
+            .collect();
+
+        let mut activities: HashMap<u64, HashSet<activity::Activity>> = HashMap::new();
+        for (id, path) in &paths {
+            let commits = self.commit_activities(*id, path).await?;
+            if !commits.is_empty() {
+                activities.entry(*id).or_default().extend(commits);
+            }
+        }
+
+        let submissions = [
+            ("issues", "~issues", OpType::CreateIssue),
+            ("pulls", "~pulls", OpType::CreatePullRequest),
+        ];
+        for (endpoint, kind, op_type) in submissions {
+            for submission in self
+                .get_all::<Submission>(&[endpoint], "submitted by me")
+                .await?
+            {
+                let Some(path) = paths.get(&submission.project_id) else {
+                    continue;
+                };
+                activities
+                    .entry(submission.project_id)
+                    .or_default()
+                    .insert(self.to_submission_activity(op_type.clone(), path, kind, &submission));
+            }
+        }
+
+        Ok(activities
+            .into_iter()
+            .map(|(id, activities)| {
+                let repo = self.to_activity_repo(&projects[&id], &paths[&id], &activities);
+                (repo, activities)
+            })
+            .collect())
+    }
+}
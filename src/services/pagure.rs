@@ -0,0 +1,401 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{parse_raw_author, ServiceClient, ServiceConfig};
+
+/// Pagure gives its dates as seconds since the epoch, in a string or a number depending on the
+/// endpoint
+struct Timestamp(DateTime<FixedOffset>);
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Seconds {
+            Text(String),
+            Number(i64),
+        }
+
+        let seconds = match Seconds::deserialize(deserializer)? {
+            Seconds::Text(text) => text.parse().map_err(serde::de::Error::custom)?,
+            Seconds::Number(number) => number,
+        };
+        DateTime::from_timestamp(seconds, 0)
+            .map(|date| Timestamp(date.fixed_offset()))
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid timestamp {}", seconds)))
+    }
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    next: Option<Url>,
+}
+
+#[derive(Deserialize)]
+struct PagureUser {
+    name: String,
+}
+
+#[derive(Default, Deserialize)]
+struct UserProjects {
+    #[serde(default)]
+    repos: Vec<PagureProject>,
+    #[serde(default)]
+    forks: Vec<PagureProject>,
+    repos_pagination: Option<Pagination>,
+    forks_pagination: Option<Pagination>,
+}
+
+#[derive(Deserialize)]
+struct PagureProject {
+    name: String,
+    namespace: Option<String>,
+    /// Like "rpms/python3" or "forks/someone/rpms/python3"
+    fullname: String,
+    /// Like "rpms/python3" or "fork/someone/rpms/python3", which is where the project is found
+    url_path: String,
+    #[serde(default)]
+    description: String,
+    date_created: Timestamp,
+    /// Who created the project, or the fork
+    user: PagureUser,
+}
+
+#[derive(Deserialize)]
+struct DayActivities {
+    activities: Vec<LogEntry>,
+}
+
+#[derive(Deserialize)]
+struct LogEntry {
+    #[serde(rename = "type")]
+    log_type: String,
+    /// The hash for commits, or the id of the issue or pull request
+    ref_id: Option<String>,
+    project: Option<PagureProject>,
+}
+
+#[derive(Deserialize)]
+struct CommitInfo {
+    /// Like "Name <email>"
+    author: String,
+    commit_time: Timestamp,
+    /// In minutes
+    #[serde(default)]
+    commit_time_offset: i32,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct IssuesPage {
+    issues: Vec<PagureIssue>,
+    pagination: Option<Pagination>,
+}
+
+impl http::LinkedPage for IssuesPage {
+    type Item = PagureIssue;
+
+    fn into_parts(self) -> (Vec<PagureIssue>, Option<Url>) {
+        (self.issues, self.pagination.and_then(|p| p.next))
+    }
+}
+
+#[derive(Deserialize)]
+struct PagureIssue {
+    id: u64,
+    title: String,
+    status: String,
+    #[serde(default)]
+    private: bool,
+    date_created: Timestamp,
+    closed_at: Option<Timestamp>,
+    closed_by: Option<PagureUser>,
+}
+
+/// A project the user did something on, along with the commits they pushed to it
+struct ProjectActivity {
+    project: PagureProject,
+    commits: HashSet<String>,
+}
+
+/// Reads what the user did on a Pagure instance. The activity log is where the commits and the
+/// projects outside of the ones of the user are found, while the issues are read from each project
+pub struct PagureClient {
+    url: Url,
+    api_url: Url,
+    username: String,
+    client: reqwest::Client,
+}
+
+impl PagureClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        // Everything read is public, the token is only needed for the rate limits
+        let mut headers = reqwest::header::HeaderMap::new();
+        if !config.token.is_empty() {
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                reqwest::header::HeaderValue::from_str(&format!("token {}", config.token))?,
+            );
+        }
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            url: config.url.clone(),
+            api_url: http::join(&config.url, &["api", "0"]),
+            username: config.username.clone(),
+        })
+    }
+
+    /// API URL of something under the project
+    fn project_endpoint(&self, project: &PagureProject, segments: &[&str]) -> Url {
+        let path: Vec<&str> = project
+            .url_path
+            .split('/')
+            .chain(segments.iter().copied())
+            .collect();
+        http::join(&self.api_url, &path)
+    }
+
+    /// Link to something under the project on the website
+    fn project_link(&self, project: &PagureProject, segments: &[&str]) -> Url {
+        let path: Vec<&str> = project
+            .url_path
+            .split('/')
+            .chain(segments.iter().copied())
+            .collect();
+        http::join(&self.url, &path)
+    }
+
+    fn is_you(&self, user: &PagureUser) -> bool {
+        user.name == self.username
+    }
+
+    /// The projects and the forks of the user
+    async fn user_projects(&self) -> anyhow::Result<Vec<PagureProject>> {
+        let mut url = http::join(&self.api_url, &["user", &self.username]);
+        url.query_pairs_mut().append_pair("per_page", "100");
+        let first: UserProjects = http::get_json(self.client.get(url))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Pagure user {} not found", self.username))?;
+
+        // The repos and the forks are paged apart from each other, on the same endpoint
+        let mut projects = first.repos;
+        let mut next = first.repos_pagination.and_then(|p| p.next);
+        while let Some(url) = next {
+            let page: UserProjects = http::get_json(self.client.get(url))
+                .await?
+                .unwrap_or_default();
+            projects.extend(page.repos);
+            next = page.repos_pagination.and_then(|p| p.next);
+        }
+        projects.extend(first.forks);
+        let mut next = first.forks_pagination.and_then(|p| p.next);
+        while let Some(url) = next {
+            let page: UserProjects = http::get_json(self.client.get(url))
+                .await?
+                .unwrap_or_default();
+            projects.extend(page.forks);
+            next = page.forks_pagination.and_then(|p| p.next);
+        }
+
+        Ok(projects)
+    }
+
+    /// Every entry of the activity log of the user, going day by day through the days with any
+    async fn activity_log(&self) -> anyhow::Result<Vec<LogEntry>> {
+        let stats: HashMap<String, u64> = http::get_json(self.client.get(http::join(
+            &self.api_url,
+            &["user", &self.username, "activity", "stats"],
+        )))
+        .await?
+        .unwrap_or_default();
+
+        let mut entries = vec![];
+        for (day, count) in stats {
+            if count == 0 {
+                continue;
+            }
+            let day_activities: Option<DayActivities> = http::get_json(self.client.get(
+                http::join(&self.api_url, &["user", &self.username, "activity", &day]),
+            ))
+            .await?;
+            entries.extend(day_activities.into_iter().flat_map(|day| day.activities));
+        }
+
+        Ok(entries)
+    }
+
+    /// The commit the log entry is about. Commits of branches that were deleted since are gone
+    async fn commit_activity(
+        &self,
+        project: &PagureProject,
+        sha: &str,
+    ) -> anyhow::Result<Option<activity::Activity>> {
+        let Some(commit) = http::get_json::<CommitInfo>(
+            self.client
+                .get(self.project_endpoint(project, &["c", sha, "info"])),
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let (name, email) = parse_raw_author(&commit.author);
+        let date = FixedOffset::east_opt(commit.commit_time_offset * 60)
+            .map_or(commit.commit_time.0, |offset| {
+                commit.commit_time.0.with_timezone(&offset)
+            });
+        Ok(Some(activity::Activity {
+            op_type: OpType::CommitRepo,
+            date,
+            content: ActivityContent::Commit(activity::Commit {
+                sha1: sha.to_string(),
+                message: commit.message.trim().to_string(),
+                author_email: email.clone(),
+                author_name: name,
+                timestamp: date,
+            }),
+            source_link: self.project_link(project, &["c", sha]),
+            username: self.username.clone(),
+            email,
+        }))
+    }
+
+    /// The issues opened by the user, and the ones they closed among them
+    async fn issue_activities(
+        &self,
+        project: &PagureProject,
+    ) -> anyhow::Result<Vec<activity::Activity>> {
+        let mut url = self.project_endpoint(project, &["issues"]);
+        url.query_pairs_mut()
+            .append_pair("author", &self.username)
+            .append_pair("status", "all")
+            .append_pair("per_page", "100");
+
+        // Projects with the issue tracker turned off give a 404, which ends the listing
+        let issues = http::get_linked(url, |url| {
+            http::get_json::<IssuesPage>(self.client.get(url))
+        })
+        .await?;
+
+        let mut activities = vec![];
+        // Private issues are only for the ones with access to the project
+        for issue in issues.iter().filter(|issue| !issue.private) {
+            let mut events = vec![(OpType::CreateIssue, issue.date_created.0)];
+            if let (Some(closed_at), Some(closed_by)) = (&issue.closed_at, &issue.closed_by) {
+                if issue.status == "Closed" && self.is_you(closed_by) {
+                    events.push((OpType::CloseIssue, closed_at.0));
+                }
+            }
+
+            activities.extend(
+                events
+                    .into_iter()
+                    .map(|(op_type, date)| activity::Activity {
+                        op_type,
+                        date,
+                        content: ActivityContent::Issue(activity::Issue {
+                            issue_id: issue.id,
+                            message: issue.title.clone(),
+                        }),
+                        source_link: self.project_link(project, &["issue", &issue.id.to_string()]),
+                        username: self.username.clone(),
+                        // Pagure keeps the emails of the accounts private
+                        email: String::new(),
+                    }),
+            );
+        }
+
+        Ok(activities)
+    }
+
+    fn to_activity_repo(&self, project: &PagureProject) -> activity::Repository {
+        let is_fork = project.url_path.starts_with("fork/");
+        // Namespaces like "rpms" group the projects of the same kind, so they are kept in the name
+        // of forks, and stand in as the owner of the projects themselves. Forks are never counted
+        // as yours, which keeps the fork of "rpms/python3" apart from the project itself
+        let (owner, name) = match (&project.namespace, is_fork) {
+            (Some(namespace), true) => (
+                project.user.name.clone(),
+                format!("{}-{}", namespace, project.name),
+            ),
+            (Some(namespace), false) => (namespace.clone(), project.name.clone()),
+            (None, _) => (project.user.name.clone(), project.name.clone()),
+        };
+        let path: Vec<&str> = project.fullname.split('/').collect();
+        let mut clone_url = http::join(&self.url, &path);
+        clone_url.set_path(&format!("{}.git", clone_url.path()));
+
+        activity::Repository {
+            owned_by_you: !is_fork && project.namespace.is_none() && self.is_you(&project.user),
+            owner,
+            name,
+            description: (!project.description.is_empty()).then(|| project.description.clone()),
+            full_name: project.fullname.clone(),
+            html_url: self.project_link(project, &[]),
+            clone_url,
+            // Only the public projects show up in the API
+            private: false,
+            created_date: project.date_created.0,
+        }
+    }
+}
+
+#[async_trait]
+impl ServiceClient for PagureClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut projects: HashMap<String, ProjectActivity> = HashMap::new();
+        for project in self.user_projects().await? {
+            projects
+                .entry(project.url_path.clone())
+                .or_insert_with(|| ProjectActivity {
+                    project,
+                    commits: HashSet::new(),
+                });
+        }
+
+        // The projects of others are only found through the log
+        for entry in self.activity_log().await? {
+            let Some(project) = entry.project else {
+                continue;
+            };
+            let project_activity =
+                projects
+                    .entry(project.url_path.clone())
+                    .or_insert_with(|| ProjectActivity {
+                        project,
+                        commits: HashSet::new(),
+                    });
+            if let ("committed", Some(sha)) = (entry.log_type.as_str(), entry.ref_id) {
+                project_activity.commits.insert(sha);
+            }
+        }
+
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+        for ProjectActivity { project, commits } in projects.into_values() {
+            let mut activities: HashSet<activity::Activity> = HashSet::new();
+            for sha in &commits {
+                activities.extend(self.commit_activity(&project, sha).await?);
+            }
+            activities.extend(self.issue_activities(&project).await?);
+
+            if !activities.is_empty() {
+                repos.insert(self.to_activity_repo(&project), activities);
+            }
+        }
+
+        Ok(repos)
+    }
+}
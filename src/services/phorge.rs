@@ -0,0 +1,533 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{ServiceClient, ServiceConfig};
+
+/// The most results a search gives at a time
+const PAGE_LIMIT: u32 = 100;
+
+/// Conduit always answers with a 200, the errors are told in the body
+#[derive(Deserialize)]
+struct ConduitResponse<T> {
+    result: Option<T>,
+    error_code: Option<String>,
+    error_info: Option<String>,
+}
+
+/// Results of one of the "*.search" methods, with the cursor to the next page
+#[derive(Deserialize)]
+struct SearchPage<T> {
+    data: Vec<SearchResult<T>>,
+    cursor: Cursor,
+}
+
+#[derive(Deserialize)]
+struct Cursor {
+    after: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult<T> {
+    id: u64,
+    phid: String,
+    fields: T,
+    #[serde(default)]
+    attachments: Attachments,
+}
+
+#[derive(Default, Deserialize)]
+struct Attachments {
+    uris: Option<UriAttachment>,
+    reviewers: Option<ReviewerAttachment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Whoami {
+    phid: String,
+    user_name: String,
+    #[serde(default)]
+    primary_email: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryFields {
+    name: String,
+    callsign: Option<String>,
+    short_name: Option<String>,
+    description: Option<Remarkup>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    date_created: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct Remarkup {
+    raw: String,
+}
+
+#[derive(Deserialize)]
+struct UriAttachment {
+    uris: Vec<SearchResult<UriFields>>,
+}
+
+#[derive(Deserialize)]
+struct UriFields {
+    uri: Effective,
+    io: Effective,
+    display: Effective,
+}
+
+#[derive(Deserialize)]
+struct Effective {
+    effective: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommitFields {
+    identifier: String,
+    #[serde(rename = "repositoryPHID")]
+    repository_phid: String,
+    author: CommitAuthor,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct CommitAuthor {
+    name: String,
+    email: String,
+    epoch: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevisionFields {
+    title: String,
+    #[serde(rename = "authorPHID")]
+    author_phid: String,
+    status: Status,
+    #[serde(rename = "repositoryPHID")]
+    repository_phid: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    date_created: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    date_modified: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct ReviewerAttachment {
+    reviewers: Vec<Reviewer>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reviewer {
+    #[serde(rename = "reviewerPHID")]
+    reviewer_phid: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskFields {
+    name: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    date_created: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    date_closed: Option<DateTime<Utc>>,
+    #[serde(rename = "closerPHID")]
+    closer_phid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    value: String,
+}
+
+/// Reads the commits, revisions and tasks of the user through the Conduit API of Phorge or
+/// Phabricator. Revisions are put in the repository they were made against, and tasks, which
+/// aren't tied to one, in a repository of their own
+pub struct PhorgeClient {
+    url: Url,
+    username: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl PhorgeClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: config.url.clone(),
+            username: config.username.clone(),
+            token: config.token.clone(),
+        })
+    }
+
+    /// Link to something on the website
+    fn link(&self, segments: &[&str]) -> Url {
+        http::join(&self.url, segments)
+    }
+
+    /// Call a Conduit method, with the parameters given as JSON
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        mut params: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        params["__conduit__"] = json!({ "token": self.token });
+        let request = self.client.post(self.link(&["api", method])).form(&[
+            ("params", params.to_string()),
+            ("output", "json".to_string()),
+            ("__conduit__", "1".to_string()),
+        ]);
+
+        let response: ConduitResponse<T> = http::send(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(code) = response.error_code {
+            anyhow::bail!(
+                "{} failed with {}: {}",
+                method,
+                code,
+                response.error_info.unwrap_or_default()
+            );
+        }
+        response
+            .result
+            .ok_or_else(|| anyhow::anyhow!("No result from {}", method))
+    }
+
+    /// Every result of a search, going through the pages
+    async fn search<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        constraints: serde_json::Value,
+        attachments: serde_json::Value,
+    ) -> anyhow::Result<Vec<SearchResult<T>>> {
+        let mut results = vec![];
+        let mut after: Option<String> = None;
+        loop {
+            let page: SearchPage<T> = self
+                .call(
+                    method,
+                    json!({
+                        "constraints": constraints,
+                        "attachments": attachments,
+                        "limit": PAGE_LIMIT,
+                        "after": after,
+                    }),
+                )
+                .await?;
+            results.extend(page.data);
+            after = page.cursor.after;
+            if after.is_none() {
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    fn to_activity_repo(&self, repo: &SearchResult<RepositoryFields>) -> activity::Repository {
+        let fields = &repo.fields;
+        let name = fields
+            .short_name
+            .clone()
+            .or_else(|| fields.callsign.as_ref().map(|c| c.to_lowercase()))
+            .unwrap_or_else(|| fields.name.replace(' ', "-"));
+        let html_url = self.link(&["diffusion", &repo.id.to_string(), ""]);
+        // The one the website shows to clone over HTTP, when there is one
+        let clone_url = repo
+            .attachments
+            .uris
+            .iter()
+            .flat_map(|uris| &uris.uris)
+            .map(|uri| &uri.fields)
+            .find(|uri| {
+                uri.display.effective == "always"
+                    && uri.io.effective != "none"
+                    && uri.uri.effective.starts_with("http")
+            })
+            .and_then(|uri| Url::parse(&uri.uri.effective).ok())
+            .unwrap_or_else(|| html_url.clone());
+
+        activity::Repository {
+            // Repositories belong to the instance, not to a user
+            owned_by_you: false,
+            owner: self.url.host_str().unwrap_or_default().to_string(),
+            name,
+            description: fields
+                .description
+                .as_ref()
+                .map(|d| d.raw.clone())
+                .filter(|d| !d.is_empty()),
+            full_name: fields.name.clone(),
+            html_url,
+            clone_url,
+            // There's no telling who can read the repository, so it is kept private
+            private: true,
+            created_date: fields.date_created.fixed_offset(),
+        }
+    }
+
+    /// A repository standing in for one of the applications, for what isn't in a repository
+    fn app_repo(
+        &self,
+        app: &str,
+        description: &str,
+        activities: &HashSet<activity::Activity>,
+    ) -> activity::Repository {
+        let html_url = self.link(&[app, ""]);
+        activity::Repository {
+            owned_by_you: false,
+            owner: self.url.host_str().unwrap_or_default().to_string(),
+            name: app.to_string(),
+            description: Some(description.to_string()),
+            full_name: app.to_string(),
+            clone_url: html_url.clone(),
+            html_url,
+            private: true,
+            created_date: activities
+                .iter()
+                .map(|activity| activity.date)
+                .min()
+                .unwrap_or_else(|| Utc::now().fixed_offset()),
+        }
+    }
+
+    fn to_issue_activity(
+        &self,
+        me: &Whoami,
+        op_type: OpType,
+        date: DateTime<Utc>,
+        monogram: String,
+        id: u64,
+        title: &str,
+    ) -> activity::Activity {
+        activity::Activity {
+            op_type,
+            date: date.fixed_offset(),
+            content: ActivityContent::Issue(activity::Issue {
+                issue_id: id,
+                message: title.to_string(),
+            }),
+            source_link: self.link(&[&monogram]),
+            username: self.username.clone(),
+            email: me.primary_email.clone(),
+        }
+    }
+
+    /// Opening the revision, and landing or abandoning it, when it is yours. Otherwise how you
+    /// reviewed it. Conduit doesn't tell when these happened, so the last change of the revision is
+    /// used for all but the opening
+    fn revision_activities(
+        &self,
+        me: &Whoami,
+        revision: &SearchResult<RevisionFields>,
+    ) -> Vec<activity::Activity> {
+        let fields = &revision.fields;
+        let mut events = vec![];
+        if fields.author_phid == me.phid {
+            events.push((OpType::CreatePullRequest, fields.date_created));
+            match fields.status.value.as_str() {
+                "published" => events.push((OpType::MergePullRequest, fields.date_modified)),
+                "abandoned" => events.push((OpType::ClosePullRequest, fields.date_modified)),
+                _ => {}
+            }
+        } else {
+            let review = revision
+                .attachments
+                .reviewers
+                .iter()
+                .flat_map(|reviewers| &reviewers.reviewers)
+                .find(|reviewer| reviewer.reviewer_phid == me.phid);
+            match review.map(|reviewer| reviewer.status.as_str()) {
+                Some("accepted") => events.push((OpType::ApprovePullRequest, fields.date_modified)),
+                Some("rejected") => events.push((OpType::RejectPullRequest, fields.date_modified)),
+                _ => {}
+            }
+        }
+
+        events
+            .into_iter()
+            .map(|(op_type, date)| {
+                self.to_issue_activity(
+                    me,
+                    op_type,
+                    date,
+                    format!("D{}", revision.id),
+                    revision.id,
+                    &fields.title,
+                )
+            })
+            .collect()
+    }
+
+    /// Opening the task, and closing it if it was you
+    fn task_activities(
+        &self,
+        me: &Whoami,
+        task: &SearchResult<TaskFields>,
+    ) -> Vec<activity::Activity> {
+        let fields = &task.fields;
+        let mut events = vec![(OpType::CreateIssue, fields.date_created)];
+        if let (Some(closed), Some(closer)) = (fields.date_closed, &fields.closer_phid) {
+            if *closer == me.phid {
+                events.push((OpType::CloseIssue, closed));
+            }
+        }
+
+        events
+            .into_iter()
+            .map(|(op_type, date)| {
+                self.to_issue_activity(
+                    me,
+                    op_type,
+                    date,
+                    format!("T{}", task.id),
+                    task.id,
+                    &fields.name,
+                )
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ServiceClient for PhorgeClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let me: Whoami = self.call("user.whoami", json!({})).await?;
+        if me.user_name != self.username {
+            anyhow::bail!(
+                "The Conduit token belongs to {}, not to {}",
+                me.user_name,
+                self.username
+            );
+        }
+
+        // Activities by the PHID of the repository they belong to, or the application
+        let mut activities: HashMap<String, HashSet<activity::Activity>> = HashMap::new();
+        let repos: HashMap<String, SearchResult<RepositoryFields>> = self
+            .search::<RepositoryFields>(
+                "diffusion.repository.search",
+                json!({}),
+                json!({ "uris": true }),
+            )
+            .await?
+            .into_iter()
+            .map(|repo| (repo.phid.clone(), repo))
+            .collect();
+
+        let commits: Vec<SearchResult<CommitFields>> = self
+            .search(
+                "diffusion.commit.search",
+                json!({ "authors": [me.phid] }),
+                json!({}),
+            )
+            .await?;
+        for commit in commits {
+            let fields = commit.fields;
+            let Some(repo) = repos.get(&fields.repository_phid) else {
+                continue;
+            };
+            let Some(date) = DateTime::from_timestamp(fields.author.epoch, 0) else {
+                continue;
+            };
+            let date = date.fixed_offset();
+            // Commits are found by the monogram of their repository, like "R12:<hash>"
+            let monogram = format!("R{}:{}", repo.id, fields.identifier);
+            activities
+                .entry(fields.repository_phid)
+                .or_default()
+                .insert(activity::Activity {
+                    op_type: OpType::CommitRepo,
+                    date,
+                    content: ActivityContent::Commit(activity::Commit {
+                        sha1: fields.identifier,
+                        message: fields.message.trim().to_string(),
+                        author_email: fields.author.email.clone(),
+                        author_name: fields.author.name,
+                        timestamp: date,
+                    }),
+                    source_link: self.link(&[&monogram]),
+                    username: self.username.clone(),
+                    email: fields.author.email,
+                });
+        }
+
+        let mut revisions: Vec<SearchResult<RevisionFields>> = self
+            .search(
+                "differential.revision.search",
+                json!({ "authorPHIDs": [me.phid] }),
+                json!({}),
+            )
+            .await?;
+        revisions.extend(
+            self.search::<RevisionFields>(
+                "differential.revision.search",
+                json!({ "reviewerPHIDs": [me.phid] }),
+                json!({ "reviewers": true }),
+            )
+            .await?
+            .into_iter()
+            .filter(|revision| revision.fields.author_phid != me.phid),
+        );
+        for revision in &revisions {
+            let key = revision
+                .fields
+                .repository_phid
+                .clone()
+                .filter(|phid| repos.contains_key(phid))
+                .unwrap_or_else(|| "differential".to_string());
+            activities
+                .entry(key)
+                .or_default()
+                .extend(self.revision_activities(&me, revision));
+        }
+
+        let tasks: Vec<SearchResult<TaskFields>> = self
+            .search(
+                "maniphest.search",
+                json!({ "authorPHIDs": [me.phid] }),
+                json!({}),
+            )
+            .await?;
+        for task in &tasks {
+            activities
+                .entry("maniphest".to_string())
+                .or_default()
+                .extend(self.task_activities(&me, task));
+        }
+
+        let mut result: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+        for (key, activities) in activities {
+            if activities.is_empty() {
+                continue;
+            }
+            let repo = match (repos.get(&key), key.as_str()) {
+                (Some(repo), _) => self.to_activity_repo(repo),
+                (None, "differential") => self.app_repo(
+                    "differential",
+                    "Revisions outside of a repository",
+                    &activities,
+                ),
+                (None, _) => self.app_repo("maniphest", "Tasks", &activities),
+            };
+            result.insert(repo, activities);
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,541 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{ServiceClient, ServiceConfig};
+
+/// The most Redmine gives in one page
+const PAGE_LIMIT: usize = 100;
+
+/// The settings of a Redmine service that the other services don't take
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct RedmineConfig {
+    /// Mirror the changesets of the project repositories too
+    #[serde(default)]
+    pub changesets: bool,
+    /// Key of the Atom feeds of the user, which is how the changesets are listed. The feeds are
+    /// read anonymously without it
+    #[serde(default)]
+    pub feed_key: Option<String>,
+}
+
+/// An Atom feed, which is how Redmine lists the activity of a project
+#[derive(Deserialize)]
+struct Feed {
+    #[serde(default, rename = "entry")]
+    entries: Vec<FeedEntry>,
+}
+
+#[derive(Deserialize)]
+struct FeedEntry {
+    /// Like "Revision 1a2b3c4d (repo): First line of the message", where "Revision" is in the
+    /// language of the user
+    title: String,
+    link: FeedLink,
+    updated: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct FeedLink {
+    #[serde(rename = "@href")]
+    href: Url,
+}
+
+/// A page of a listing, which is under the name of what is listed
+#[derive(Deserialize)]
+struct Page<T> {
+    #[serde(alias = "issues", alias = "projects", alias = "issue_statuses")]
+    items: Vec<T>,
+    /// Left out of the listings that aren't paged
+    total_count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct UserResponse {
+    user: RedmineUser,
+}
+
+#[derive(Deserialize)]
+struct RedmineUser {
+    id: u64,
+    login: String,
+    #[serde(default)]
+    mail: String,
+}
+
+#[derive(Deserialize)]
+struct ProjectResponse {
+    project: RedmineProject,
+}
+
+#[derive(Deserialize)]
+struct RedmineProject {
+    id: u64,
+    name: String,
+    identifier: String,
+    #[serde(default)]
+    description: String,
+    /// Left out by the older versions, which only list what can be seen anyway
+    is_public: Option<bool>,
+    parent: Option<Reference>,
+    created_on: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct Reference {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct IssueStatus {
+    id: u64,
+    #[serde(default)]
+    is_closed: bool,
+}
+
+#[derive(Deserialize)]
+struct IssueResponse {
+    issue: RedmineIssue,
+}
+
+#[derive(Deserialize)]
+struct RedmineIssue {
+    id: u64,
+    project: Reference,
+    author: Reference,
+    subject: String,
+    #[serde(default)]
+    is_private: bool,
+    created_on: DateTime<FixedOffset>,
+    #[serde(default)]
+    journals: Vec<Journal>,
+    #[serde(default)]
+    changesets: Vec<Changeset>,
+}
+
+#[derive(Deserialize)]
+struct Journal {
+    id: u64,
+    user: Reference,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    private_notes: bool,
+    created_on: DateTime<FixedOffset>,
+    #[serde(default)]
+    details: Vec<JournalDetail>,
+}
+
+#[derive(Deserialize)]
+struct JournalDetail {
+    property: String,
+    name: String,
+    old_value: Option<String>,
+    new_value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Changeset {
+    revision: String,
+    /// The account the committer is mapped to, if any
+    user: Option<Reference>,
+    #[serde(default)]
+    comments: String,
+    committed_on: DateTime<FixedOffset>,
+}
+
+/// Reads the issues the user created or updated on a Redmine instance, and optionally the
+/// changesets linked to their account. Time entries are left out, as logged hours aren't
+/// contributions to anything that could be mirrored, and only have the day they were spent on
+pub struct RedmineClient {
+    url: Url,
+    username: String,
+    changesets: bool,
+    feed_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl RedmineClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "X-Redmine-API-Key",
+            reqwest::header::HeaderValue::from_str(&config.token)?,
+        );
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        let redmine = config.redmine.clone().unwrap_or_default();
+        Ok(Self {
+            client,
+            url: config.url.clone(),
+            username: config.username.clone(),
+            changesets: redmine.changesets,
+            feed_key: redmine.feed_key,
+        })
+    }
+
+    /// URL made out of the segments, which are escaped, where the last one gets the ".json"
+    /// extension for the API
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.link(segments);
+        url.set_path(&format!("{}.json", url.path()));
+        url
+    }
+
+    /// Link to something on the website
+    fn link(&self, segments: &[&str]) -> Url {
+        http::join(&self.url, segments)
+    }
+
+    /// GET a resource. Redmine answers with a 403 for what the user can't see, like the issues of
+    /// archived projects, which is taken the same as a 404
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: Url) -> anyhow::Result<Option<T>> {
+        match http::get_json(self.client.get(url)).await {
+            Err(e)
+                if e.downcast_ref::<reqwest::Error>()
+                    .and_then(reqwest::Error::status)
+                    == Some(StatusCode::FORBIDDEN) =>
+            {
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Every item of a listing, a page at a time
+    async fn get_all<T: serde::de::DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<Vec<T>> {
+        let mut items = vec![];
+        loop {
+            let mut url = self.endpoint(segments);
+            url.query_pairs_mut()
+                .extend_pairs(params)
+                .append_pair("offset", &items.len().to_string())
+                .append_pair("limit", &PAGE_LIMIT.to_string());
+
+            let Some(page) = self.get::<Page<T>>(url).await? else {
+                break;
+            };
+            let fetched = page.items.len();
+            items.extend(page.items);
+            if fetched == 0 || page.total_count.is_none_or(|total| items.len() >= total) {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// The changesets linked to the user in the repositories of the project. The REST API has no
+    /// listing of them, so they come from the activity feed of the project, which only has the
+    /// latest ones. The older ones are picked up through the issues they reference
+    async fn project_changesets(
+        &self,
+        user: &RedmineUser,
+        project: &RedmineProject,
+    ) -> anyhow::Result<Vec<Changeset>> {
+        let mut url = self.link(&["projects", &project.identifier, "activity.atom"]);
+        url.query_pairs_mut()
+            .append_pair("show_changesets", "1")
+            .append_pair("with_subprojects", "0")
+            .append_pair("user_id", &user.id.to_string());
+        if let Some(key) = &self.feed_key {
+            url.query_pairs_mut().append_pair("key", key);
+        }
+
+        // Projects without a repository, or without the activity module, have no feed
+        let Some(response) = http::get_found(self.client.get(url)).await? else {
+            return Ok(vec![]);
+        };
+        let feed: Feed = quick_xml::de::from_str(&response.text().await?)?;
+
+        Ok(feed
+            .entries
+            .into_iter()
+            .filter_map(|entry| {
+                // The link is the only place with the whole revision, like
+                // "/projects/alpha/repository/repo/revisions/<revision>"
+                let mut segments = entry.link.href.path_segments()?.rev();
+                let revision = segments.next()?.to_string();
+                (segments.next()? == "revisions").then(|| Changeset {
+                    revision,
+                    user: Some(Reference { id: user.id }),
+                    comments: entry
+                        .title
+                        .split_once(": ")
+                        .map(|(_, message)| message.to_string())
+                        .unwrap_or_default(),
+                    committed_on: entry.updated,
+                })
+            })
+            .collect())
+    }
+
+    fn changeset_activity(
+        &self,
+        user: &RedmineUser,
+        project: &RedmineProject,
+        changeset: &Changeset,
+    ) -> activity::Activity {
+        activity::Activity {
+            op_type: OpType::CommitRepo,
+            date: changeset.committed_on,
+            content: ActivityContent::Commit(activity::Commit {
+                sha1: changeset.revision.clone(),
+                message: changeset.comments.trim().to_string(),
+                author_email: user.mail.clone(),
+                author_name: user.login.clone(),
+                timestamp: changeset.committed_on,
+            }),
+            source_link: self.link(&[
+                "projects",
+                &project.identifier,
+                "repository",
+                "revisions",
+                &changeset.revision,
+            ]),
+            username: self.username.clone(),
+            email: user.mail.clone(),
+        }
+    }
+
+    /// The activities of the user on the issue: opening it, commenting on it and closing it, and the
+    /// changesets linked to it
+    fn issue_activities(
+        &self,
+        user: &RedmineUser,
+        closed_statuses: &HashSet<String>,
+        issue: &RedmineIssue,
+        project: &RedmineProject,
+    ) -> Vec<activity::Activity> {
+        let mut activities = vec![];
+        let issue_link = self.link(&["issues", &issue.id.to_string()]);
+        let issue_activity = |op_type, date, content, source_link| activity::Activity {
+            op_type,
+            date,
+            content,
+            source_link,
+            username: self.username.clone(),
+            email: user.mail.clone(),
+        };
+        let issue_content = || {
+            ActivityContent::Issue(activity::Issue {
+                issue_id: issue.id,
+                message: issue.subject.clone(),
+            })
+        };
+
+        if issue.author.id == user.id {
+            activities.push(issue_activity(
+                OpType::CreateIssue,
+                issue.created_on,
+                issue_content(),
+                issue_link.clone(),
+            ));
+        }
+
+        for journal in &issue.journals {
+            if journal.user.id != user.id {
+                continue;
+            }
+
+            let closed = journal.details.iter().any(|detail| {
+                detail.property == "attr"
+                    && detail.name == "status_id"
+                    && detail
+                        .new_value
+                        .as_ref()
+                        .is_some_and(|status| closed_statuses.contains(status))
+                    && !detail
+                        .old_value
+                        .as_ref()
+                        .is_some_and(|status| closed_statuses.contains(status))
+            });
+            if closed {
+                activities.push(issue_activity(
+                    OpType::CloseIssue,
+                    journal.created_on,
+                    issue_content(),
+                    issue_link.clone(),
+                ));
+            }
+
+            // Private notes are only for the ones allowed to see them
+            if !journal.notes.trim().is_empty() && !journal.private_notes {
+                let mut source_link = issue_link.clone();
+                source_link.set_fragment(Some(&format!("change-{}", journal.id)));
+                activities.push(issue_activity(
+                    OpType::CommentIssue,
+                    journal.created_on,
+                    ActivityContent::Comment(activity::Comment {
+                        comment_id: journal.id,
+                        issue_id: issue.id,
+                        message: issue.subject.clone(),
+                    }),
+                    source_link,
+                ));
+            }
+        }
+
+        for changeset in &issue.changesets {
+            if changeset.user.as_ref().is_none_or(|u| u.id != user.id) {
+                continue;
+            }
+            activities.push(self.changeset_activity(user, project, changeset));
+        }
+
+        activities
+    }
+
+    fn to_activity_repo(
+        &self,
+        project: &RedmineProject,
+        projects: &HashMap<u64, RedmineProject>,
+    ) -> activity::Repository {
+        // Subprojects are kept under their parent, and top level projects apart from the ones of
+        // other instances by the host
+        let owner = project
+            .parent
+            .as_ref()
+            .and_then(|parent| projects.get(&parent.id))
+            .map(|parent| parent.identifier.clone())
+            .unwrap_or_else(|| self.url.host_str().unwrap_or_default().to_string());
+        let html_url = self.link(&["projects", &project.identifier]);
+
+        activity::Repository {
+            // Projects belong to the instance, not to a user
+            owned_by_you: false,
+            owner,
+            name: project.identifier.clone(),
+            description: (!project.description.is_empty()).then(|| project.description.clone()),
+            full_name: project.name.clone(),
+            clone_url: html_url.clone(),
+            html_url,
+            private: !project.is_public.unwrap_or(false),
+            created_date: project.created_on,
+        }
+    }
+}
+
+#[async_trait]
+impl ServiceClient for RedmineClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let user = self
+            .get::<UserResponse>(self.endpoint(&["users", "current"]))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Redmine user {} not found", self.username))?
+            .user;
+        if user.login != self.username {
+            anyhow::bail!(
+                "The Redmine API key belongs to {}, not to {}",
+                user.login,
+                self.username
+            );
+        }
+
+        // The ids of the statuses are what the journals tell about
+        let closed_statuses: HashSet<String> = self
+            .get_all::<IssueStatus>(&["issue_statuses"], &[])
+            .await?
+            .into_iter()
+            .filter(|status| status.is_closed)
+            .map(|status| status.id.to_string())
+            .collect();
+        let mut projects: HashMap<u64, RedmineProject> = self
+            .get_all::<RedmineProject>(&["projects"], &[])
+            .await?
+            .into_iter()
+            .map(|project| (project.id, project))
+            .collect();
+
+        // The issues created by the user, and the ones they updated, of any status
+        let user_id = user.id.to_string();
+        let mut issue_ids: Vec<u64> = vec![];
+        for filter in ["author_id", "updated_by"] {
+            let issues: Vec<Reference> = self
+                .get_all(&["issues"], &[(filter, &user_id), ("status_id", "*")])
+                .await?;
+            issue_ids.extend(issues.into_iter().map(|issue| issue.id));
+        }
+        issue_ids.sort_unstable();
+        issue_ids.dedup();
+
+        let mut activities: HashMap<u64, HashSet<activity::Activity>> = HashMap::new();
+        for id in issue_ids {
+            let mut url = self.endpoint(&["issues", &id.to_string()]);
+            url.query_pairs_mut().append_pair(
+                "include",
+                if self.changesets {
+                    "journals,changesets"
+                } else {
+                    "journals"
+                },
+            );
+            let Some(IssueResponse { issue }) = self.get(url).await? else {
+                continue;
+            };
+            // Private issues are only for the ones allowed to see them
+            if issue.is_private {
+                continue;
+            }
+
+            let project_id = issue.project.id;
+            if let Entry::Vacant(entry) = projects.entry(project_id) {
+                // Archived projects are left out of the listing
+                match self
+                    .get::<ProjectResponse>(self.endpoint(&["projects", &project_id.to_string()]))
+                    .await?
+                {
+                    Some(response) => entry.insert(response.project),
+                    None => continue,
+                };
+            }
+
+            let issue_activities =
+                self.issue_activities(&user, &closed_statuses, &issue, &projects[&project_id]);
+            activities
+                .entry(project_id)
+                .or_default()
+                .extend(issue_activities);
+        }
+
+        if self.changesets {
+            for project in projects.values() {
+                let project_activities = activities.entry(project.id).or_default();
+                // The ones referencing an issue came with it already, with their whole message
+                let known: HashSet<String> = project_activities
+                    .iter()
+                    .filter_map(|activity| match &activity.content {
+                        ActivityContent::Commit(commit) => Some(commit.sha1.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                for changeset in self.project_changesets(&user, project).await? {
+                    if !known.contains(&changeset.revision) {
+                        project_activities
+                            .insert(self.changeset_activity(&user, project, &changeset));
+                    }
+                }
+            }
+        }
+
+        Ok(activities
+            .into_iter()
+            .filter(|(_, activities)| !activities.is_empty())
+            .map(|(id, activities)| (self.to_activity_repo(&projects[&id], &projects), activities))
+            .collect())
+    }
+}
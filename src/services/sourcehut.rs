@@ -0,0 +1,399 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::activity::{self, ActivityContent, OpType};
+use crate::http;
+
+use super::{ServiceClient, ServiceConfig};
+
+// https://man.sr.ht/git.sr.ht/graphql.md
+const REPOS_QUERY: &str = "
+query repos($username: String!, $cursor: Cursor) {
+  user(username: $username) {
+    email
+    repositories(cursor: $cursor) {
+      results { name description visibility created }
+      cursor
+    }
+  }
+}";
+
+const LOG_QUERY: &str = "
+query log($username: String!, $name: String!, $cursor: Cursor) {
+  user(username: $username) {
+    repository(name: $name) {
+      log(cursor: $cursor) {
+        results { id message author { name email time } }
+        cursor
+      }
+    }
+  }
+}";
+
+// https://man.sr.ht/todo.sr.ht/graphql.md
+// The events are those of the user the token belongs to, on any tracker
+const EVENTS_QUERY: &str = "
+query events($cursor: Cursor) {
+  events(cursor: $cursor) {
+    results {
+      ticket {
+        id subject created submitter { canonicalName }
+        tracker { name description visibility created owner { canonicalName } }
+      }
+    }
+    cursor
+  }
+}";
+
+#[derive(Deserialize)]
+struct GraphqlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+/// A page of results, with the cursor to the next one
+#[derive(Deserialize)]
+struct Page<T> {
+    results: Vec<T>,
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserData<T> {
+    user: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct ReposUser {
+    email: String,
+    repositories: Page<SrhtRepo>,
+}
+
+/// A repo on git.sr.ht or a tracker on todo.sr.ht, which have the same fields
+#[derive(Deserialize)]
+struct SrhtRepo {
+    name: String,
+    description: Option<String>,
+    visibility: String,
+    created: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct LogUser {
+    repository: Option<LogRepo>,
+}
+
+#[derive(Deserialize)]
+struct LogRepo {
+    log: Page<SrhtCommit>,
+}
+
+#[derive(Deserialize)]
+struct SrhtCommit {
+    id: String,
+    message: String,
+    author: SrhtSignature,
+}
+
+#[derive(Deserialize)]
+struct SrhtSignature {
+    name: String,
+    email: String,
+    time: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+struct EventsData {
+    events: Page<SrhtEvent>,
+}
+
+#[derive(Deserialize)]
+struct SrhtEvent {
+    ticket: SrhtTicket,
+}
+
+#[derive(Deserialize)]
+struct SrhtTicket {
+    id: u64,
+    subject: String,
+    created: DateTime<FixedOffset>,
+    submitter: SrhtEntity,
+    tracker: SrhtTracker,
+}
+
+#[derive(Deserialize)]
+struct SrhtTracker {
+    #[serde(flatten)]
+    repo: SrhtRepo,
+    owner: SrhtEntity,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SrhtEntity {
+    canonical_name: String,
+}
+
+/// Reads the repos on git.sr.ht and the ticket history on todo.sr.ht through their GraphQL APIs. Each of
+/// the sr.ht services lives on its own subdomain of the configured URL
+pub struct SourcehutClient {
+    git_url: Url,
+    todo_url: Url,
+    /// Without the "~" in front
+    username: String,
+    client: reqwest::Client,
+}
+
+/// URL of one of the sr.ht services, like https://git.sr.ht for "git" on https://sr.ht
+fn service_url(url: &Url, service: &str) -> anyhow::Result<Url> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid base URL"))?;
+    let mut service_url = url.clone();
+    service_url.set_host(Some(&format!("{}.{}", service, host)))?;
+    service_url.set_path("");
+    Ok(service_url)
+}
+
+impl SourcehutClient {
+    pub fn new(config: &ServiceConfig) -> anyhow::Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            reqwest::header::HeaderValue::from_str(&format!("Bearer {}", config.token))?,
+        );
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            git_url: service_url(&config.url, "git")?,
+            todo_url: service_url(&config.url, "todo")?,
+            username: config.username.trim_start_matches('~').to_string(),
+        })
+    }
+
+    /// Run a GraphQL query against one of the services
+    async fn query<T: serde::de::DeserializeOwned>(
+        &self,
+        service_url: &Url,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let url = service_url.join("query")?;
+        let response: GraphqlResponse<T> = http::send(self.client.post(url).json(&json!({
+            "query": query,
+            "variables": variables,
+        })))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+        if !response.errors.is_empty() {
+            let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+            anyhow::bail!("{}", messages.join(", "));
+        }
+        response
+            .data
+            .ok_or_else(|| anyhow::anyhow!("No data in the response of {}", service_url))
+    }
+
+    fn canonical_name(&self) -> String {
+        format!("~{}", self.username)
+    }
+
+    /// `owner` is the canonical name of the owner, with the "~" in front
+    fn to_activity_repo(
+        &self,
+        service_url: &Url,
+        owner: &str,
+        repo: &SrhtRepo,
+    ) -> activity::Repository {
+        let html_url = link(service_url, owner, &[&repo.name]);
+        activity::Repository {
+            owned_by_you: owner == self.canonical_name(),
+            owner: owner.trim_start_matches('~').to_string(),
+            name: repo.name.clone(),
+            description: repo.description.clone().filter(|d| !d.is_empty()),
+            full_name: format!("{}/{}", owner, repo.name),
+            clone_url: html_url.clone(),
+            html_url,
+            // Unlisted repos are only hidden from the listings, not private, but anyone who
+            // hasn't got the link shouldn't find them either
+            private: repo.visibility != "PUBLIC",
+            created_date: repo.created,
+        }
+    }
+
+    /// The repos on git.sr.ht, along with the email of the user
+    async fn repos(&self) -> anyhow::Result<(String, Vec<SrhtRepo>)> {
+        let mut email;
+        let mut repos = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let data: UserData<ReposUser> = self
+                .query(
+                    &self.git_url,
+                    REPOS_QUERY,
+                    json!({ "username": self.username, "cursor": cursor }),
+                )
+                .await?;
+            let user = data
+                .user
+                .ok_or_else(|| anyhow::anyhow!("Sourcehut user {} not found", self.username))?;
+
+            email = user.email;
+            repos.extend(user.repositories.results);
+            cursor = user.repositories.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok((email, repos))
+    }
+
+    /// The whole log of a repo, newest first
+    async fn log(&self, name: &str) -> anyhow::Result<Vec<SrhtCommit>> {
+        let mut commits = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let data: UserData<LogUser> = self
+                .query(
+                    &self.git_url,
+                    LOG_QUERY,
+                    json!({ "username": self.username, "name": name, "cursor": cursor }),
+                )
+                .await?;
+            let Some(repo) = data.user.and_then(|user| user.repository) else {
+                break;
+            };
+
+            commits.extend(repo.log.results);
+            cursor = repo.log.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(commits)
+    }
+
+    /// The tickets of all the events of the user, on their own trackers and on those of others.
+    /// A ticket comes up once for each of its events
+    async fn event_tickets(&self) -> anyhow::Result<Vec<SrhtTicket>> {
+        let mut tickets = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let data: EventsData = self
+                .query(&self.todo_url, EVENTS_QUERY, json!({ "cursor": cursor }))
+                .await?;
+
+            tickets.extend(data.events.results.into_iter().map(|event| event.ticket));
+            cursor = data.events.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(tickets)
+    }
+}
+
+/// Link to something under a user on one of the services, `owner` having the "~" in front
+fn link(service_url: &Url, owner: &str, segments: &[&str]) -> Url {
+    http::join(service_url, &[&[owner], segments].concat())
+}
+
+#[async_trait]
+impl ServiceClient for SourcehutClient {
+    async fn get_repos(
+        &self,
+    ) -> anyhow::Result<HashMap<activity::Repository, HashSet<activity::Activity>>> {
+        let mut repos: HashMap<activity::Repository, HashSet<activity::Activity>> = HashMap::new();
+
+        let (email, srht_repos) = self.repos().await?;
+        // Trackers usually have the same name as the repo they belong to
+        let mut repos_by_name: HashMap<String, activity::Repository> = HashMap::new();
+
+        for srht_repo in srht_repos {
+            let repo = self.to_activity_repo(&self.git_url, &self.canonical_name(), &srht_repo);
+            let activities: HashSet<activity::Activity> = self
+                .log(&srht_repo.name)
+                .await?
+                .into_iter()
+                .filter(|commit| commit.author.email.to_lowercase() == email.to_lowercase())
+                .map(|commit| activity::Activity {
+                    op_type: OpType::CommitRepo,
+                    date: commit.author.time,
+                    source_link: link(
+                        &self.git_url,
+                        &self.canonical_name(),
+                        &[&srht_repo.name, "commit", &commit.id],
+                    ),
+                    content: ActivityContent::Commit(activity::Commit {
+                        sha1: commit.id,
+                        message: commit.message.trim().to_string(),
+                        author_email: commit.author.email.clone(),
+                        author_name: commit.author.name,
+                        timestamp: commit.author.time,
+                    }),
+                    username: self.username.clone(),
+                    email: commit.author.email,
+                })
+                .collect();
+
+            repos_by_name.insert(srht_repo.name, repo.clone());
+            if !activities.is_empty() {
+                repos.insert(repo, activities);
+            }
+        }
+
+        let tickets = self
+            .event_tickets()
+            .await?
+            .into_iter()
+            .filter(|ticket| ticket.submitter.canonical_name == self.canonical_name());
+        for ticket in tickets {
+            let tracker = &ticket.tracker;
+            let owner = &tracker.owner.canonical_name;
+            let activity = activity::Activity {
+                op_type: OpType::CreateIssue,
+                date: ticket.created,
+                source_link: link(
+                    &self.todo_url,
+                    owner,
+                    &[&tracker.repo.name, &ticket.id.to_string()],
+                ),
+                content: ActivityContent::Issue(activity::Issue {
+                    issue_id: ticket.id,
+                    message: ticket.subject.clone(),
+                }),
+                username: self.username.clone(),
+                email: email.clone(),
+            };
+
+            let repo = repos_by_name
+                .get(&tracker.repo.name)
+                .filter(|_| *owner == self.canonical_name())
+                .cloned()
+                .unwrap_or_else(|| self.to_activity_repo(&self.todo_url, owner, &tracker.repo));
+            repos.entry(repo).or_default().insert(activity);
+        }
+
+        Ok(repos)
+    }
+}
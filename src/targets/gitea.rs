@@ -0,0 +1,277 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use super::{MirrorRepo, MirrorTarget, TargetConfig, TargetType};
+use crate::{
+    config::GitConfig,
+    error::Error,
+    git::{self, Git, MARK_STRING},
+    http,
+};
+
+const CODEBERG_URL: &str = "https://codeberg.org";
+
+#[derive(Deserialize)]
+struct GiteaUser {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaRepo {
+    full_name: String,
+    html_url: String,
+    clone_url: Option<String>,
+    ssh_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GiteaIssue {
+    number: u64,
+    title: String,
+    created_at: DateTime<chrono::FixedOffset>,
+}
+
+/// Mirrors to Gitea, Forgejo and Codeberg, which share the same API
+pub struct GiteaTarget {
+    config: GitConfig,
+    url: Url,
+    api_url: Url,
+    client: reqwest::Client,
+    git: Git,
+}
+
+impl GiteaTarget {
+    pub async fn new(config: &TargetConfig) -> Result<Self, Error> {
+        let url = match (&config.url, &config.target_type) {
+            (Some(url), _) => url.clone(),
+            (None, TargetType::Codeberg) => Url::parse(CODEBERG_URL).unwrap(),
+            (None, target_type) => {
+                return Err(Error::Config(anyhow::anyhow!(
+                    "{:?} targets need the url of the instance",
+                    target_type
+                )))
+            }
+        };
+        let api_url = http::join(&url, &["api", "v1"]);
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            reqwest::header::HeaderValue::from_str(&format!("token {}", config.git.token))
+                .map_err(|e| Error::Config(e.into()))?,
+        );
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .map_err(|e| Error::Config(e.into()))?;
+
+        // Keep the workspaces of different instances apart
+        let workspace = git::workspace("gitea", url.host_str().unwrap_or_default());
+
+        let mut gitea = Self {
+            config: config.git.clone(),
+            git: Git::new(config.git.clone(), workspace.clone()),
+            url,
+            api_url,
+            client,
+        };
+
+        // Get the appropriate email
+        if gitea.config.email.is_none() {
+            let user: GiteaUser =
+                http::get_json(gitea.client.get(http::join(&gitea.api_url, &["user"])))
+                    .await
+                    .map_err(Error::mirror_api)?
+                    .ok_or_else(|| Error::Auth(anyhow::anyhow!("Gitea user not found")))?;
+            gitea.config.email = Some(user.email).filter(|email| !email.is_empty());
+            gitea.git = Git::new(gitea.config.clone(), workspace);
+        }
+        if gitea.config.email.is_none() {
+            return Err(Error::Config(anyhow::anyhow!(
+                "Unable to get gitea email. Specify this in the settings.toml file"
+            )));
+        }
+
+        Ok(gitea)
+    }
+
+    /// API URL of something in the repo
+    fn repo_endpoint(&self, repo: &MirrorRepo, segments: &[&str]) -> Url {
+        let path: Vec<&str> = ["repos"]
+            .into_iter()
+            .chain(repo.full_name.split('/'))
+            .chain(segments.iter().copied())
+            .collect();
+        http::join(&self.api_url, &path)
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        url: Url,
+        body: &serde_json::Value,
+    ) -> anyhow::Result<T> {
+        Ok(http::send(self.client.post(url).json(body))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    fn to_mirror_repo(repo: GiteaRepo) -> MirrorRepo {
+        MirrorRepo {
+            full_name: repo.full_name,
+            html_url: repo.html_url,
+            clone_url: repo.clone_url,
+            ssh_url: repo.ssh_url,
+        }
+    }
+
+    /// All the issues, leaving out pull requests
+    async fn issues(
+        &self,
+        repo: &MirrorRepo,
+        since: Option<DateTime<chrono::FixedOffset>>,
+    ) -> anyhow::Result<Vec<GiteaIssue>> {
+        let limit = 50;
+        let mut page = 1;
+        let mut issues = vec![];
+
+        loop {
+            let mut url = self.repo_endpoint(repo, &["issues"]);
+            url.query_pairs_mut()
+                .append_pair("state", "all")
+                .append_pair("type", "issues")
+                .append_pair("limit", &limit.to_string())
+                .append_pair("page", &page.to_string());
+            if let Some(since) = since {
+                url.query_pairs_mut()
+                    .append_pair("since", &since.to_rfc3339());
+            }
+
+            let result: Vec<GiteaIssue> = http::get_json(self.client.get(url))
+                .await?
+                .unwrap_or_default();
+            // Gitea API will return an empty array if the limit + page goes beyond the issues
+            if result.is_empty() {
+                break;
+            }
+            issues.extend(result);
+            page += 1;
+        }
+
+        Ok(issues)
+    }
+}
+
+#[async_trait]
+impl MirrorTarget for GiteaTarget {
+    fn id(&self) -> String {
+        format!(
+            "{}/{}",
+            self.url.as_str().trim_end_matches('/'),
+            self.config.username
+        )
+    }
+
+    fn config(&self) -> &GitConfig {
+        &self.config
+    }
+
+    fn git(&self) -> &Git {
+        &self.git
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<MirrorRepo>, Error> {
+        let url = http::join(&self.api_url, &["repos", &self.config.username, name]);
+        let repo: Option<GiteaRepo> = http::get_json(self.client.get(url))
+            .await
+            .map_err(Error::mirror_api)?;
+        Ok(repo.map(Self::to_mirror_repo))
+    }
+
+    // To verify if it is a mirror, check if the README on the default branch ends with MARK_STRING
+    async fn is_mirror(&self, repo: &MirrorRepo) -> Result<bool, Error> {
+        let url = self.repo_endpoint(repo, &["raw", "README.md"]);
+        // Without a README, it can't have the marker
+        let Some(response) = http::get_found(self.client.get(url))
+            .await
+            .map_err(Error::mirror_api)?
+        else {
+            return Ok(false);
+        };
+        let readme = response
+            .text()
+            .await
+            .map_err(|e| Error::mirror_api(e.into()))?;
+
+        Ok(readme.trim_end().ends_with(MARK_STRING))
+    }
+
+    async fn create_repo(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        private: bool,
+    ) -> Result<MirrorRepo, Error> {
+        let mut req = json!({
+            "name": name,
+            "private": private,
+        });
+        if let Some(desc) = description {
+            req["description"] = serde_json::Value::String(desc.to_string());
+        };
+
+        let repo: GiteaRepo = self
+            .post(http::join(&self.api_url, &["user", "repos"]), &req)
+            .await
+            .map_err(Error::mirror_api)?;
+
+        Ok(Self::to_mirror_repo(repo))
+    }
+
+    async fn last_issue_date(
+        &self,
+        repo: &MirrorRepo,
+    ) -> Result<Option<DateTime<chrono::FixedOffset>>, Error> {
+        let issues = self.issues(repo, None).await.map_err(Error::mirror_api)?;
+        Ok(issues.iter().map(|issue| issue.created_at).max())
+    }
+
+    async fn issue_titles_since(
+        &self,
+        repo: &MirrorRepo,
+        since: DateTime<chrono::FixedOffset>,
+    ) -> Result<Vec<String>, Error> {
+        println!("Getting issues from repo: {}", repo.full_name);
+
+        let issues = self
+            .issues(repo, Some(since))
+            .await
+            .map_err(Error::mirror_api)?;
+        Ok(issues.into_iter().map(|issue| issue.title).collect())
+    }
+
+    // The API doesn't allow backdating issues, so they get the current date
+    async fn create_issue(
+        &self,
+        repo: &MirrorRepo,
+        title: &str,
+        body: &str,
+        _date: DateTime<chrono::FixedOffset>,
+    ) -> Result<u64, Error> {
+        let req = json!({
+            "title": title,
+            "body": body,
+        });
+
+        let issue: GiteaIssue = self
+            .post(self.repo_endpoint(repo, &["issues"]), &req)
+            .await
+            .map_err(Error::mirror_api)?;
+
+        Ok(issue.number)
+    }
+}
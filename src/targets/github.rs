@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde_json::json;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use octocrab::Octocrab;
+
+use super::{MirrorRepo, MirrorTarget};
+use crate::{
+    config::GitConfig,
+    error::Error,
+    git::{Git, MARK_STRING},
+    http,
+};
+
+// GitHub recommends waiting at least a second between requests that create content
+const ISSUE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct GithubClient {
+    config: GitConfig,
+    // Might come in handy
+    // client: reqwest::Client,
+    octocrab: Octocrab,
+    git: Git,
+    /// Earliest time the next issue can be created
+    next_issue: tokio::sync::Mutex<Instant>,
+}
+
+impl GithubClient {
+    pub async fn new(mut github_config: GitConfig) -> Result<Self, Error> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            reqwest::header::HeaderValue::from_str(&format!("token {}", github_config.token))
+                .unwrap(),
+        );
+
+        let octocrab = octocrab::instance().user_access_token(&*github_config.token)?;
+
+        // Get the appropriate email
+        if github_config.email.is_none() {
+            let emails = http::github(&octocrab, true, || async {
+                octocrab
+                    .users(&github_config.username)
+                    .emails()
+                    .list()
+                    .await
+            })
+            .await?
+            .items;
+
+            // Grab the @users.noreply.github.com
+            for email in emails {
+                if email.email.ends_with("@users.noreply.github.com") {
+                    github_config.email = Some(email.email);
+                    break;
+                }
+            }
+        }
+        if github_config.email.is_none() {
+            return Err(Error::Config(anyhow::anyhow!(
+                "Unable to get github email. Specify this in the settings.toml file"
+            )));
+        }
+
+        let git = Git::new(github_config.clone(), std::env::temp_dir());
+
+        Ok(Self {
+            config: github_config,
+            octocrab,
+            git,
+            next_issue: tokio::sync::Mutex::new(Instant::now()),
+        })
+    }
+
+    fn to_mirror_repo(repo: octocrab::models::Repository) -> MirrorRepo {
+        MirrorRepo {
+            full_name: repo.full_name.unwrap_or(repo.name),
+            html_url: repo.html_url.map(String::from).unwrap_or_default(),
+            clone_url: repo.clone_url.map(String::from),
+            ssh_url: repo.ssh_url,
+        }
+    }
+
+    async fn last_issue(
+        &self,
+        repo: &MirrorRepo,
+    ) -> Result<Option<octocrab::models::issues::Issue>, Error> {
+        let (owner, name) = split_full_name(repo);
+        // Pull requests are also issues, so we need to scroll and find the first issue
+        let mut page: u32 = 1;
+        let per_page = 50;
+        loop {
+            let results = http::github(&self.octocrab, true, || async {
+                self.octocrab
+                    .issues(owner, name)
+                    .list()
+                    .page(page)
+                    .per_page(per_page)
+                    .sort(octocrab::params::issues::Sort::Created)
+                    .direction(octocrab::params::Direction::Descending)
+                    .send()
+                    .await
+            })
+            .await?;
+
+            if results.total_count.is_none_or(|r| r == 0) || results.items.is_empty() {
+                return Ok(None);
+            }
+
+            for res in results {
+                if res.pull_request.is_none() {
+                    return Ok(Some(res));
+                }
+            }
+
+            page += 1;
+        }
+    }
+
+    async fn issues_since(
+        &self,
+        repo: &MirrorRepo,
+        since: DateTime<chrono::FixedOffset>,
+    ) -> Result<Vec<octocrab::models::issues::Issue>, Error> {
+        let (owner, name) = split_full_name(repo);
+        let mut page: u32 = 0;
+        let per_page = 50;
+
+        let mut issues = vec![];
+
+        println!("Getting issues from repo: {}", name);
+
+        loop {
+            let results = http::github(&self.octocrab, true, || async {
+                self.octocrab
+                    .issues(owner, name)
+                    .list()
+                    .since(since)
+                    .page(page)
+                    .per_page(per_page)
+                    .sort(octocrab::params::issues::Sort::Created)
+                    .direction(octocrab::params::Direction::Descending)
+                    .send()
+                    .await
+            })
+            .await?;
+
+            let res: Vec<octocrab::models::issues::Issue> = results.items.into_iter().collect();
+
+            if res.is_empty() {
+                break;
+            }
+
+            issues.extend(res.into_iter().filter(|res| res.pull_request.is_none()));
+
+            page += 1;
+        }
+
+        Ok(issues)
+    }
+}
+
+#[async_trait]
+impl MirrorTarget for GithubClient {
+    fn id(&self) -> String {
+        format!("https://github.com/{}", self.config.username)
+    }
+
+    fn config(&self) -> &GitConfig {
+        &self.config
+    }
+
+    fn git(&self) -> &Git {
+        &self.git
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<MirrorRepo>, Error> {
+        match http::github(&self.octocrab, true, || async {
+            self.octocrab.repos(&self.config.username, name).get().await
+        })
+        .await
+        {
+            Ok(repo) => Ok(Some(Self::to_mirror_repo(repo))),
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code == 404 => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // To verify if it is a mirror, check if the MARK_STRING
+    async fn is_mirror(&self, repo: &MirrorRepo) -> Result<bool, Error> {
+        let (owner, name) = split_full_name(repo);
+        let readme = match http::github(&self.octocrab, true, || async {
+            self.octocrab.repos(owner, name).get_readme().send().await
+        })
+        .await
+        {
+            Ok(readme) => readme.decoded_content(),
+            // Without a README, it can't have the marker
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code == 404 => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(readme.is_some_and(|content| content.trim_end().ends_with(MARK_STRING)))
+    }
+
+    async fn create_repo(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        private: bool,
+    ) -> Result<MirrorRepo, Error> {
+        let mut req = json!({
+            "name": name,
+            "private": private,
+        });
+        if let Some(desc) = description {
+            req["description"] = serde_json::Value::String(desc.to_string());
+        };
+
+        let body = serde_json::to_value(&req).unwrap();
+
+        let new_repo: octocrab::models::Repository =
+            http::github(&self.octocrab, false, || async {
+                self.octocrab.post("/user/repos", Some(&body)).await
+            })
+            .await?;
+
+        Ok(Self::to_mirror_repo(new_repo))
+    }
+
+    async fn last_issue_date(
+        &self,
+        repo: &MirrorRepo,
+    ) -> Result<Option<DateTime<chrono::FixedOffset>>, Error> {
+        Ok(self
+            .last_issue(repo)
+            .await?
+            .map(|issue| issue.created_at.into()))
+    }
+
+    async fn issue_titles_since(
+        &self,
+        repo: &MirrorRepo,
+        since: DateTime<chrono::FixedOffset>,
+    ) -> Result<Vec<String>, Error> {
+        Ok(self
+            .issues_since(repo, since)
+            .await?
+            .into_iter()
+            .map(|issue| issue.title)
+            .collect())
+    }
+
+    // The REST API doesn't allow backdating issues, so they get the current date
+    async fn create_issue(
+        &self,
+        repo: &MirrorRepo,
+        title: &str,
+        body: &str,
+        _date: DateTime<chrono::FixedOffset>,
+    ) -> Result<u64, Error> {
+        let (owner, name) = split_full_name(repo);
+        // Creating content too quickly trips GitHub's abuse detection. Issues are created one at a
+        // time, and when a secondary rate limit is hit, the lock is held while it is waited out,
+        // pausing all issue creation
+        let mut next_issue = self.next_issue.lock().await;
+        tokio::time::sleep_until(*next_issue).await;
+
+        let issue = http::github(&self.octocrab, false, || async {
+            self.octocrab
+                .issues(owner, name)
+                .create(title)
+                .body(body)
+                .send()
+                .await
+        })
+        .await;
+        *next_issue = Instant::now() + ISSUE_INTERVAL;
+
+        Ok(issue?.number)
+    }
+}
+
+fn split_full_name(repo: &MirrorRepo) -> (&str, &str) {
+    repo.full_name
+        .split_once('/')
+        .unwrap_or(("", repo.full_name.as_str()))
+}
@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use super::{MirrorRepo, MirrorTarget, TargetConfig};
+use crate::{
+    config::GitConfig,
+    error::Error,
+    git::{self, Git, MARK_STRING},
+    http,
+};
+
+const GITLAB_URL: &str = "https://gitlab.com";
+
+#[derive(Deserialize)]
+struct GitlabUser {
+    email: Option<String>,
+    commit_email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitlabProject {
+    path_with_namespace: String,
+    web_url: String,
+    http_url_to_repo: Option<String>,
+    ssh_url_to_repo: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitlabIssue {
+    iid: u64,
+    title: String,
+    created_at: DateTime<chrono::FixedOffset>,
+}
+
+pub struct GitlabClient {
+    config: GitConfig,
+    url: Url,
+    api_url: Url,
+    client: reqwest::Client,
+    git: Git,
+}
+
+impl GitlabClient {
+    pub async fn new(config: &TargetConfig) -> Result<Self, Error> {
+        let url = match &config.url {
+            Some(url) => url.clone(),
+            None => Url::parse(GITLAB_URL).unwrap(),
+        };
+        let api_url = http::join(&url, &["api", "v4"]);
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "PRIVATE-TOKEN",
+            reqwest::header::HeaderValue::from_str(&config.git.token)
+                .map_err(|e| Error::Config(e.into()))?,
+        );
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .map_err(|e| Error::Config(e.into()))?;
+
+        // Keep the workspaces of different instances apart
+        let workspace = git::workspace("gitlab", url.host_str().unwrap_or_default());
+
+        let mut gitlab = Self {
+            config: config.git.clone(),
+            git: Git::new(config.git.clone(), workspace.clone()),
+            url,
+            api_url,
+            client,
+        };
+
+        // Get the appropriate email
+        if gitlab.config.email.is_none() {
+            let user: GitlabUser =
+                http::get_json(gitlab.client.get(http::join(&gitlab.api_url, &["user"])))
+                    .await
+                    .map_err(Error::mirror_api)?
+                    .ok_or_else(|| Error::Auth(anyhow::anyhow!("GitLab user not found")))?;
+            gitlab.config.email = user.commit_email.or(user.email);
+            gitlab.git = Git::new(gitlab.config.clone(), workspace);
+        }
+        if gitlab.config.email.is_none() {
+            return Err(Error::Config(anyhow::anyhow!(
+                "Unable to get gitlab email. Specify this in the settings.toml file"
+            )));
+        }
+
+        Ok(gitlab)
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        url: Url,
+        body: &serde_json::Value,
+    ) -> anyhow::Result<T> {
+        Ok(http::send(self.client.post(url).json(body))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    fn to_mirror_repo(project: GitlabProject) -> MirrorRepo {
+        MirrorRepo {
+            full_name: project.path_with_namespace,
+            html_url: project.web_url,
+            clone_url: project.http_url_to_repo,
+            ssh_url: project.ssh_url_to_repo,
+        }
+    }
+
+    async fn issues(
+        &self,
+        repo: &MirrorRepo,
+        since: Option<DateTime<chrono::FixedOffset>>,
+        per_page: u32,
+        page: u32,
+    ) -> anyhow::Result<Vec<GitlabIssue>> {
+        // Project paths are passed whole, as GitLab accepts a URL encoded "namespace/name" in
+        // place of the project ID
+        let mut url = http::join(&self.api_url, &["projects", &repo.full_name, "issues"]);
+        url.query_pairs_mut()
+            .append_pair("order_by", "created_at")
+            .append_pair("sort", "desc")
+            .append_pair("per_page", &per_page.to_string())
+            .append_pair("page", &page.to_string());
+        if let Some(since) = since {
+            url.query_pairs_mut()
+                .append_pair("created_after", &since.to_rfc3339());
+        }
+
+        Ok(http::get_json(self.client.get(url))
+            .await?
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl MirrorTarget for GitlabClient {
+    fn id(&self) -> String {
+        format!(
+            "{}/{}",
+            self.url.as_str().trim_end_matches('/'),
+            self.config.username
+        )
+    }
+
+    fn config(&self) -> &GitConfig {
+        &self.config
+    }
+
+    fn git(&self) -> &Git {
+        &self.git
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<MirrorRepo>, Error> {
+        let path = format!("{}/{}", self.config.username, name);
+        let url = http::join(&self.api_url, &["projects", &path]);
+        let project: Option<GitlabProject> = http::get_json(self.client.get(url))
+            .await
+            .map_err(Error::mirror_api)?;
+        Ok(project.map(Self::to_mirror_repo))
+    }
+
+    // To verify if it is a mirror, check if the README on the default branch ends with MARK_STRING
+    async fn is_mirror(&self, repo: &MirrorRepo) -> Result<bool, Error> {
+        let mut url = http::join(
+            &self.api_url,
+            &[
+                "projects",
+                &repo.full_name,
+                "repository",
+                "files",
+                "README.md",
+                "raw",
+            ],
+        );
+        url.query_pairs_mut().append_pair("ref", "HEAD");
+
+        // Without a README, it can't have the marker
+        let Some(response) = http::get_found(self.client.get(url))
+            .await
+            .map_err(Error::mirror_api)?
+        else {
+            return Ok(false);
+        };
+        let readme = response
+            .text()
+            .await
+            .map_err(|e| Error::mirror_api(e.into()))?;
+
+        Ok(readme.trim_end().ends_with(MARK_STRING))
+    }
+
+    async fn create_repo(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        private: bool,
+    ) -> Result<MirrorRepo, Error> {
+        let mut req = json!({
+            "name": name,
+            "path": name,
+            "visibility": if private { "private" } else { "public" },
+        });
+        if let Some(desc) = description {
+            req["description"] = serde_json::Value::String(desc.to_string());
+        };
+
+        let project: GitlabProject = self
+            .post(http::join(&self.api_url, &["projects"]), &req)
+            .await
+            .map_err(Error::mirror_api)?;
+
+        Ok(Self::to_mirror_repo(project))
+    }
+
+    async fn last_issue_date(
+        &self,
+        repo: &MirrorRepo,
+    ) -> Result<Option<DateTime<chrono::FixedOffset>>, Error> {
+        let issues = self
+            .issues(repo, None, 1, 1)
+            .await
+            .map_err(Error::mirror_api)?;
+        Ok(issues.first().map(|issue| issue.created_at))
+    }
+
+    async fn issue_titles_since(
+        &self,
+        repo: &MirrorRepo,
+        since: DateTime<chrono::FixedOffset>,
+    ) -> Result<Vec<String>, Error> {
+        let per_page = 100;
+        let mut page = 1;
+        let mut titles = vec![];
+
+        println!("Getting issues from repo: {}", repo.full_name);
+
+        loop {
+            let issues = self
+                .issues(repo, Some(since), per_page, page)
+                .await
+                .map_err(Error::mirror_api)?;
+            if issues.is_empty() {
+                break;
+            }
+            titles.extend(issues.into_iter().map(|issue| issue.title));
+            page += 1;
+        }
+
+        Ok(titles)
+    }
+
+    // GitLab lets project owners backdate issues, which the mirror always is
+    async fn create_issue(
+        &self,
+        repo: &MirrorRepo,
+        title: &str,
+        body: &str,
+        date: DateTime<chrono::FixedOffset>,
+    ) -> Result<u64, Error> {
+        let req = json!({
+            "title": title,
+            "description": body,
+            "created_at": date.to_rfc3339(),
+        });
+
+        let issue: GitlabIssue = self
+            .post(
+                http::join(&self.api_url, &["projects", &repo.full_name, "issues"]),
+                &req,
+            )
+            .await
+            .map_err(Error::mirror_api)?;
+
+        Ok(issue.iid)
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use sha1_smol::Sha1;
+use std::fs;
+use std::path::PathBuf;
+
+use super::{MirrorRepo, MirrorTarget, TargetConfig};
+use crate::{
+    config::GitConfig,
+    error::Error,
+    git::{self, Git, MARK_STRING},
+};
+
+/// An issue in the sidecar file next to a bare repo
+#[derive(Deserialize, Serialize)]
+struct LocalIssue {
+    number: u64,
+    title: String,
+    body: String,
+    created_at: DateTime<chrono::FixedOffset>,
+}
+
+/// Keeps the mirrors as bare repos in a local directory, with the issues in a JSON file next to
+/// each of them
+pub struct LocalTarget {
+    config: GitConfig,
+    path: PathBuf,
+    git: Git,
+}
+
+impl LocalTarget {
+    pub fn new(config: &TargetConfig) -> Result<Self, Error> {
+        let path = config.path.as_ref().ok_or_else(|| {
+            Error::Config(anyhow::anyhow!(
+                "Local targets need a path to keep the repos in"
+            ))
+        })?;
+        let path = fs::create_dir_all(path)
+            .and_then(|_| fs::canonicalize(path))
+            .with_context(|| format!("Failed to create {}", path.display()))
+            .map_err(Error::Config)?;
+
+        let mut config = config.git.clone();
+        // Nothing leaves the machine, so any email will do
+        if config.email.is_none() {
+            config.email = Some(format!("{}@localhost", config.username));
+        }
+
+        // Keep the workspaces of different directories apart
+        let workspace = git::workspace(
+            "local",
+            &Sha1::from(path.to_string_lossy().as_bytes())
+                .digest()
+                .to_string()[..8],
+        );
+
+        Ok(Self {
+            git: Git::new(config.clone(), workspace),
+            config,
+            path,
+        })
+    }
+
+    fn repo_path(&self, name: &str) -> PathBuf {
+        self.path.join(format!("{}.git", name))
+    }
+
+    fn issues_path(&self, repo: &MirrorRepo) -> PathBuf {
+        self.path.join(format!("{}.issues.json", name_of(repo)))
+    }
+
+    fn to_mirror_repo(&self, name: &str) -> MirrorRepo {
+        let path = self.repo_path(name).to_string_lossy().into_owned();
+        MirrorRepo {
+            full_name: format!("{}/{}", self.config.username, name),
+            html_url: format!("file://{}", path),
+            clone_url: Some(path.clone()),
+            ssh_url: Some(path),
+        }
+    }
+
+    fn read_issues(&self, repo: &MirrorRepo) -> anyhow::Result<Vec<LocalIssue>> {
+        let path = self.issues_path(repo);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read issues {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse issues {}", path.display()))
+    }
+
+    fn write_issues(&self, repo: &MirrorRepo, issues: &[LocalIssue]) -> anyhow::Result<()> {
+        // Same as the ledger, an interrupted run can't leave a truncated file
+        let path = self.issues_path(repo);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(issues)?)
+            .with_context(|| format!("Failed to write issues {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write issues {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MirrorTarget for LocalTarget {
+    fn id(&self) -> String {
+        format!("file://{}", self.path.display())
+    }
+
+    fn config(&self) -> &GitConfig {
+        &self.config
+    }
+
+    fn git(&self) -> &Git {
+        &self.git
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<MirrorRepo>, Error> {
+        Ok(self
+            .repo_path(name)
+            .exists()
+            .then(|| self.to_mirror_repo(name)))
+    }
+
+    async fn is_mirror(&self, repo: &MirrorRepo) -> Result<bool, Error> {
+        let readme = self
+            .git
+            .read_bare_file(&self.repo_path(name_of(repo)), "README.md")
+            .await
+            .map_err(Error::Git)?;
+        Ok(readme.is_some_and(|content| content.trim_end().ends_with(MARK_STRING)))
+    }
+
+    // There is no one to hide the repos from, so they are created the same whether private or not
+    async fn create_repo(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        _private: bool,
+    ) -> Result<MirrorRepo, Error> {
+        let path = self.repo_path(name);
+        self.git.init_bare(&path).await.map_err(Error::Git)?;
+        if let Some(description) = description {
+            // Shown by gitweb, cgit and the like
+            fs::write(path.join("description"), description)
+                .context("Failed to write the description")
+                .map_err(Error::Git)?;
+        }
+
+        Ok(self.to_mirror_repo(name))
+    }
+
+    async fn last_issue_date(
+        &self,
+        repo: &MirrorRepo,
+    ) -> Result<Option<DateTime<chrono::FixedOffset>>, Error> {
+        let issues = self.read_issues(repo).map_err(Error::MirrorApi)?;
+        Ok(issues.iter().map(|issue| issue.created_at).max())
+    }
+
+    async fn issue_titles_since(
+        &self,
+        repo: &MirrorRepo,
+        since: DateTime<chrono::FixedOffset>,
+    ) -> Result<Vec<String>, Error> {
+        let issues = self.read_issues(repo).map_err(Error::MirrorApi)?;
+        Ok(issues
+            .into_iter()
+            .filter(|issue| issue.created_at >= since)
+            .map(|issue| issue.title)
+            .collect())
+    }
+
+    async fn create_issue(
+        &self,
+        repo: &MirrorRepo,
+        title: &str,
+        body: &str,
+        date: DateTime<chrono::FixedOffset>,
+    ) -> Result<u64, Error> {
+        let mut issues = self.read_issues(repo).map_err(Error::MirrorApi)?;
+        let number = issues.iter().map(|issue| issue.number).max().unwrap_or(0) + 1;
+        issues.push(LocalIssue {
+            number,
+            title: title.to_string(),
+            body: body.to_string(),
+            created_at: date,
+        });
+        self.write_issues(repo, &issues).map_err(Error::MirrorApi)?;
+
+        Ok(number)
+    }
+}
+
+/// Name of the repo, without the owner
+fn name_of(repo: &MirrorRepo) -> &str {
+    repo.full_name
+        .split_once('/')
+        .map_or(repo.full_name.as_str(), |(_, name)| name)
+}
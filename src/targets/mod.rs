@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use url::Url;
+
+use crate::{config::GitConfig, error::Error, git::Git};
+use gitea::GiteaTarget;
+use github::GithubClient;
+use gitlab::GitlabClient;
+use local::LocalTarget;
+
+pub mod gitea;
+pub mod github;
+pub mod gitlab;
+pub mod local;
+
+/// Where the activities are mirrored to
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetType {
+    Github,
+    Gitlab,
+    /// Bare repos in a local directory
+    Local,
+    Gitea,
+    Forgejo,
+    Codeberg,
+}
+impl TargetType {
+    pub async fn connect(&self, config: &TargetConfig) -> Result<Box<dyn MirrorTarget>, Error> {
+        match self {
+            TargetType::Github => Ok(Box::new(GithubClient::new(config.git.clone()).await?)),
+            TargetType::Gitlab => Ok(Box::new(GitlabClient::new(config).await?)),
+            TargetType::Local => Ok(Box::new(LocalTarget::new(config)?)),
+            // Forgejo is a fork of Gitea, and still has the same API
+            TargetType::Gitea | TargetType::Forgejo | TargetType::Codeberg => {
+                Ok(Box::new(GiteaTarget::new(config).await?))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TargetConfig {
+    pub target_type: TargetType,
+    /// Base URL of self-hosted targets. GitLab defaults to https://gitlab.com, and Codeberg to
+    /// https://codeberg.org
+    #[serde(default)]
+    pub url: Option<Url>,
+    /// Directory of the bare repos, for local targets
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(flatten)]
+    pub git: GitConfig,
+}
+
+/// A mirror repo on a target
+#[derive(Clone)]
+pub struct MirrorRepo {
+    /// "owner/name" on the target
+    pub full_name: String,
+    pub html_url: String,
+    pub clone_url: Option<String>,
+    pub ssh_url: Option<String>,
+}
+
+#[async_trait]
+pub trait MirrorTarget: Send + Sync {
+    /// Identifies the account on the target, like "https://github.com/username"
+    fn id(&self) -> String;
+
+    fn config(&self) -> &GitConfig;
+
+    /// The workspace the mirror repos are committed to before pushing
+    fn git(&self) -> &Git;
+
+    /// Look up a repo by name, under the configured user
+    async fn find_repo(&self, name: &str) -> Result<Option<MirrorRepo>, Error>;
+
+    /// If the repo was created by this application, and not by the user
+    async fn is_mirror(&self, repo: &MirrorRepo) -> Result<bool, Error>;
+
+    /// Create an empty repo. The initial commit with the marker is pushed afterwards
+    async fn create_repo(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        private: bool,
+    ) -> Result<MirrorRepo, Error>;
+
+    /// When the most recent issue on the mirror was created
+    async fn last_issue_date(
+        &self,
+        repo: &MirrorRepo,
+    ) -> Result<Option<DateTime<chrono::FixedOffset>>, Error>;
+
+    /// Titles of the issues created on the mirror since the date
+    async fn issue_titles_since(
+        &self,
+        repo: &MirrorRepo,
+        since: DateTime<chrono::FixedOffset>,
+    ) -> Result<Vec<String>, Error>;
+
+    /// Open an issue, returning its number
+    async fn create_issue(
+        &self,
+        repo: &MirrorRepo,
+        title: &str,
+        body: &str,
+        date: DateTime<chrono::FixedOffset>,
+    ) -> Result<u64, Error>;
+
+    /// Commit the README change to the local workspace, returning the commit sha
+    async fn add_commit(
+        &self,
+        repo: &MirrorRepo,
+        message: String,
+        content: String,
+        date: DateTime<chrono::FixedOffset>,
+    ) -> Result<String, Error> {
+        self.git()
+            .add_commit(repo, message, content, date)
+            .await
+            .map_err(Error::Git)
+    }
+
+    /// Push the commits in the local workspace, returning how many were pushed
+    async fn push(&self, repo: &MirrorRepo) -> Result<u32, Error> {
+        let unpushed_commits = self
+            .git()
+            .unpushed_commits(repo)
+            .await
+            .map_err(Error::Git)?;
+        if unpushed_commits > 0 {
+            self.git().push(repo).await.map_err(Error::Git)?;
+        }
+        Ok(unpushed_commits)
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::Context;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinSet;
+
+use crate::{
+    config::Config, daemon::shutdown_signal, error::Error, ledger::Ledger, mirror::Mirrors,
+    services::ServiceConfig,
+};
+
+// Forgejo sends both its own and the Gitea headers, Gitea only sends its own
+const EVENT_HEADERS: [&str; 2] = ["X-Forgejo-Event", "X-Gitea-Event"];
+const SIGNATURE_HEADERS: [&str; 2] = ["X-Forgejo-Signature", "X-Gitea-Signature"];
+
+struct WebhookState {
+    config: Arc<Config>,
+    mirrors: Arc<Mirrors>,
+    ledger: Arc<Mutex<Ledger>>,
+    syncs: Mutex<JoinSet<()>>,
+}
+
+/// Listen for webhooks, and sync the activities in them right away
+pub async fn serve(
+    config: Arc<Config>,
+    mirrors: Arc<Mirrors>,
+    ledger: Arc<Mutex<Ledger>>,
+) -> Result<(), Error> {
+    let Some(webhook) = &config.webhook else {
+        return Err(Error::Config(anyhow::anyhow!(
+            "The [webhook] section is missing from the config file"
+        )));
+    };
+    let listener = tokio::net::TcpListener::bind(&webhook.listen)
+        .await
+        .with_context(|| format!("Unable to listen on {}", webhook.listen))
+        .map_err(Error::Server)?;
+    println!(
+        "Listening for webhooks on http://{}/webhook",
+        listener.local_addr().map_err(|e| Error::Server(e.into()))?
+    );
+
+    let state = Arc::new(WebhookState {
+        config: config.clone(),
+        mirrors,
+        ledger,
+        syncs: Mutex::new(JoinSet::new()),
+    });
+    let app = Router::new()
+        .route("/webhook", post(receive))
+        .with_state(state.clone());
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .map_err(|e| Error::Server(e.into()))?;
+
+    // Let the repos that are being synced finish, so nothing is left half-pushed
+    state.mirrors.stop();
+    let mut syncs = std::mem::take(&mut *state.syncs.lock().unwrap());
+    if !syncs.is_empty() {
+        println!("Shutting down, waiting for the webhook syncs to finish");
+    }
+    while syncs.join_next().await.is_some() {}
+
+    Ok(())
+}
+
+async fn receive(
+    State(state): State<Arc<WebhookState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let Some(event) = find_header(&headers, &EVENT_HEADERS) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let Some(signature) = find_signature(&headers) else {
+        return StatusCode::UNAUTHORIZED;
+    };
+
+    // Whichever service has the secret the payload was signed with, is the one that sent it
+    let Some(service) = state
+        .config
+        .services
+        .iter()
+        .find(|service| verify_signature(service, &body, &signature))
+    else {
+        return StatusCode::UNAUTHORIZED;
+    };
+    let Some(client) = &service.client else {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    };
+
+    let repos = match client.parse_webhook(event, &body) {
+        Ok(repos) => repos,
+        Err(e) => {
+            eprintln!(
+                "Invalid {} webhook from {:?} ({}): {:#}",
+                event, service.service_type, service.url, e
+            );
+            return StatusCode::UNPROCESSABLE_ENTITY;
+        }
+    };
+    if repos.values().all(HashSet::is_empty) {
+        return StatusCode::OK;
+    }
+    println!(
+        "Received {} webhook from {:?} ({})",
+        event, service.service_type, service.url
+    );
+
+    let mirrors = state.mirrors.clone();
+    let ledger = state.ledger.clone();
+    let mut syncs = state.syncs.lock().unwrap();
+    // Forget about the syncs that are already done
+    while syncs.try_join_next().is_some() {}
+    syncs.spawn(async move {
+        let report = mirrors.sync(repos, &ledger).await;
+        println!("\n{}", report);
+    });
+
+    StatusCode::ACCEPTED
+}
+
+fn find_header<'a>(headers: &'a HeaderMap, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .and_then(|value| value.to_str().ok())
+}
+
+/// The signature of the payload, which is sent hex encoded
+fn find_signature(headers: &HeaderMap) -> Option<Vec<u8>> {
+    find_header(headers, &SIGNATURE_HEADERS).and_then(|s| hex::decode(s).ok())
+}
+
+fn verify_signature(service: &ServiceConfig, body: &[u8], signature: &[u8]) -> bool {
+    let Some(secret) = &service.webhook_secret else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(body);
+    mac.verify_slice(signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{Auth, ServiceType};
+
+    const BODY: &[u8] = br#"{"ref":"refs/heads/main"}"#;
+
+    fn service(webhook_secret: Option<&str>) -> ServiceConfig {
+        ServiceConfig {
+            service_type: ServiceType::Gitea,
+            username: "me".to_string(),
+            url: "https://gitea.example.com".parse().unwrap(),
+            token: String::new(),
+            auth: Auth::default(),
+            webhook_secret: webhook_secret.map(str::to_string),
+            redmine: None,
+            client: None,
+        }
+    }
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn headers(name: &str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            value.parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn valid_signatures_are_accepted() {
+        let headers = headers("X-Gitea-Signature", &sign("secret", BODY));
+        let signature = find_signature(&headers).unwrap();
+        assert!(verify_signature(&service(Some("secret")), BODY, &signature));
+    }
+
+    #[test]
+    fn forgejo_signatures_are_found_too() {
+        let headers = headers("X-Forgejo-Signature", &sign("secret", BODY));
+        let signature = find_signature(&headers).unwrap();
+        assert!(verify_signature(&service(Some("secret")), BODY, &signature));
+    }
+
+    #[test]
+    fn tampered_payloads_are_rejected() {
+        let signature = hex::decode(sign("secret", BODY)).unwrap();
+        let tampered = br#"{"ref":"refs/heads/evil"}"#;
+        assert!(!verify_signature(
+            &service(Some("secret")),
+            tampered,
+            &signature
+        ));
+        // Nor is a payload signed with another secret
+        let signature = hex::decode(sign("other", BODY)).unwrap();
+        assert!(!verify_signature(
+            &service(Some("secret")),
+            BODY,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn services_without_a_secret_accept_nothing() {
+        let signature = hex::decode(sign("", BODY)).unwrap();
+        assert!(!verify_signature(&service(None), BODY, &signature));
+    }
+
+    #[test]
+    fn missing_or_malformed_signatures_are_not_found() {
+        assert!(find_signature(&HeaderMap::new()).is_none());
+        assert!(find_signature(&headers("X-Gitea-Signature", "not hex")).is_none());
+    }
+}